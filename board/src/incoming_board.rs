@@ -7,26 +7,68 @@ use crate::{
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Request {
+    #[serde(default)]
+    game: Option<IGame>,
     board: IBoard,
     you: IBattlesnake,
     turn: u32,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+pub struct IGame {
+    ruleset: IRuleset,
+    #[serde(default)]
+    map: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct IRuleset {
+    name: String,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct IBoard {
     width: u32,
     height: u32,
     food: Vec<Coordinate>,
+    #[serde(default)]
+    hazards: Vec<Coordinate>,
     snakes: Vec<IBattlesnake>,
 }
 #[derive(Deserialize, Debug, Clone)]
 pub struct IBattlesnake {
     id: String,
+    #[serde(default)]
+    name: String,
     health: u8,
     body: Vec<Coordinate>,
 }
 
 impl Request {
+    pub fn turn(&self) -> u32 {
+        self.turn
+    }
+
+    /// Name of the ruleset, if the request says which one it was played under.
+    pub fn ruleset_name(&self) -> Option<&str> {
+        self.game.as_ref().map(|x| x.ruleset.name.as_str())
+    }
+
+    /// Name of the map, if the request says which one it was played on.
+    pub fn map_name(&self) -> Option<&str> {
+        self.game.as_ref().and_then(|x| x.map.as_deref())
+    }
+
+    /// Width and height of the board.
+    pub fn board_size(&self) -> (u32, u32) {
+        (self.board.width, self.board.height)
+    }
+
+    /// Names of the snakes on the board.
+    pub fn snake_names(&self) -> impl Iterator<Item = &str> {
+        self.board.snakes.iter().map(|x| x.name.as_str())
+    }
+
     pub fn into_usable(&self) -> Game {
         let mut snakes = vec![];
         for snake in &self.board.snakes {
@@ -45,6 +87,7 @@ impl Request {
                 height: self.board.height,
                 snakes,
                 food: self.board.food.clone(),
+                hazards: self.board.hazards.clone(),
             },
         }
     }
@@ -3,7 +3,12 @@ use std::ops::Add;
 use serde::{Deserialize, Serialize};
 
 pub mod incoming_board;
+pub mod rules;
+pub mod symmetry;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
 pub mod useful_board;
+pub mod zobrist;
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Coordinate {
     pub x: i8,
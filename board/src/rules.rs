@@ -0,0 +1,201 @@
+//! Advancing a game by one turn under standard rules.
+//!
+//! Food spawning is left to the caller, since it needs a source of randomness. Hazards are
+//! carried along but do no damage.
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    useful_board::{Board, Game},
+    Coordinate,
+};
+
+/// Health a snake has after eating, and at the start of a game.
+pub const MAX_HEALTH: u8 = 100;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Move {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Move {
+    pub const ALL: [Move; 4] = [Move::Up, Move::Down, Move::Left, Move::Right];
+
+    /// The step a head takes, with y pointing up as in the API.
+    pub fn offset(&self) -> Coordinate {
+        match self {
+            Move::Up => Coordinate::new(0, 1),
+            Move::Down => Coordinate::new(0, -1),
+            Move::Left => Coordinate::new(-1, 0),
+            Move::Right => Coordinate::new(1, 0),
+        }
+    }
+}
+
+impl Board {
+    pub fn contains(&self, coord: Coordinate) -> bool {
+        coord.x >= 0
+            && coord.y >= 0
+            && (coord.x as u32) < self.width
+            && (coord.y as u32) < self.height
+    }
+
+    /// Squares with neither a snake nor food on them.
+    pub fn unoccupied(&self) -> Vec<Coordinate> {
+        let mut squares = vec![];
+        for x in 0..self.width as i8 {
+            for y in 0..self.height as i8 {
+                let square = Coordinate::new(x, y);
+                if !self.food.contains(&square)
+                    && self
+                        .snakes
+                        .iter()
+                        .all(|snake| !snake.body.contains(&square))
+                {
+                    squares.push(square);
+                }
+            }
+        }
+        squares
+    }
+}
+
+impl Game {
+    /// Play one turn, with `moves[i]` the move of the snake in slot `i`.
+    ///
+    /// Snakes move, lose a point of health and eat, then every snake that starved, left the
+    /// board, ran into a body or lost a head-to-head is removed from the board.
+    pub fn advance(&mut self, moves: &[Move]) {
+        assert_eq!(moves.len(), self.board.snakes.len());
+        let board = &mut self.board;
+        for (snake, step) in board.snakes.iter_mut().zip(moves) {
+            let head = snake.body[0] + step.offset();
+            snake.body.insert(0, head);
+            snake.body.pop();
+            snake.health = snake.health.saturating_sub(1);
+        }
+        // every head on a food eats it, and only then is it removed
+        let mut eaten = vec![];
+        for snake in &mut board.snakes {
+            if board.food.contains(&snake.body[0]) {
+                eaten.push(snake.body[0]);
+                snake.health = MAX_HEALTH;
+                snake.body.push(*snake.body.last().unwrap());
+            }
+        }
+        board.food.retain(|x| !eaten.contains(x));
+        // every elimination is decided on the board after moving, before anyone is removed
+        let eliminated: Vec<bool> = board
+            .snakes
+            .iter()
+            .enumerate()
+            .map(|(i, snake)| {
+                let head = snake.body[0];
+                snake.health == 0
+                    || !board.contains(head)
+                    || board
+                        .snakes
+                        .iter()
+                        .any(|other| other.body[1..].contains(&head))
+                    || board.snakes.iter().enumerate().any(|(j, other)| {
+                        j != i && other.body[0] == head && other.body.len() >= snake.body.len()
+                    })
+            })
+            .collect();
+        let mut eliminated = eliminated.into_iter();
+        board.snakes.retain(|_| !eliminated.next().unwrap());
+        self.turn += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{game, snake};
+
+    use super::*;
+
+    #[test]
+    fn moves_and_eats() {
+        let mut position = game(
+            "a",
+            vec![
+                snake("a", &[(1, 1), (1, 0), (0, 0)]),
+                snake("b", &[(5, 5), (5, 5), (5, 5)]),
+            ],
+            &[(1, 2)],
+        );
+        position.advance(&[Move::Up, Move::Left]);
+        let (a, b) = (&position.board.snakes[0], &position.board.snakes[1]);
+        assert_eq!(a.body, snake("a", &[(1, 2), (1, 1), (1, 0), (1, 0)]).body);
+        assert_eq!(a.health, MAX_HEALTH);
+        assert_eq!(b.body, snake("b", &[(4, 5), (5, 5), (5, 5)]).body);
+        assert_eq!(b.health, 89);
+        assert!(position.board.food.is_empty());
+        assert_eq!(position.turn, 1);
+    }
+
+    #[test]
+    fn eliminates_snakes() {
+        // the shorter snake loses the head-to-head
+        let mut position = game(
+            "a",
+            vec![
+                snake("a", &[(1, 3), (0, 3), (0, 2)]),
+                snake("b", &[(3, 3), (4, 3)]),
+            ],
+            &[],
+        );
+        position.advance(&[Move::Right, Move::Left]);
+        assert_eq!(position.board.snakes.len(), 1);
+        assert_eq!(position.board.snakes[0].id, "a");
+
+        // equal lengths both die, and so does a snake leaving the board
+        let mut position = game(
+            "a",
+            vec![
+                snake("a", &[(1, 3), (0, 3)]),
+                snake("b", &[(3, 3), (4, 3)]),
+                snake("c", &[(10, 10), (10, 9)]),
+            ],
+            &[],
+        );
+        position.advance(&[Move::Right, Move::Left, Move::Up]);
+        assert!(position.board.snakes.is_empty());
+
+        // running into a body, even the snake's own
+        let mut position = game(
+            "a",
+            vec![
+                snake("a", &[(1, 1), (1, 2), (2, 2), (2, 1), (2, 0)]),
+                snake("b", &[(4, 4), (4, 3)]),
+            ],
+            &[],
+        );
+        position.advance(&[Move::Right, Move::Up]);
+        assert_eq!(position.board.snakes.len(), 1);
+        assert_eq!(position.board.snakes[0].id, "b");
+    }
+
+    #[test]
+    fn both_heads_eat_the_same_food() {
+        // the longer snake survives the head-to-head and is fed, whatever slot it is in
+        let mut position = game(
+            "a",
+            vec![
+                snake("a", &[(1, 3), (0, 3)]),
+                snake("b", &[(3, 3), (4, 3), (5, 3)]),
+            ],
+            &[(2, 3), (6, 6)],
+        );
+        position.advance(&[Move::Right, Move::Left]);
+        assert_eq!(position.board.snakes.len(), 1);
+        let b = &position.board.snakes[0];
+        assert_eq!(b.id, "b");
+        assert_eq!(b.health, MAX_HEALTH);
+        assert_eq!(b.body.len(), 4);
+        assert_eq!(position.board.food, [Coordinate::new(6, 6)]);
+    }
+}
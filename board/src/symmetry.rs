@@ -0,0 +1,100 @@
+//! The 8 dihedral symmetries of a rectangular board.
+//!
+//! Under standard rules a position and its rotations / reflections are equivalent, so these
+//! are used to check evaluations for invariance and to augment training data.
+use crate::{
+    useful_board::{Board, Game},
+    Coordinate,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Symmetry {
+    Identity,
+    /// rotate 90 degrees counterclockwise
+    Rotate90,
+    Rotate180,
+    /// rotate 270 degrees counterclockwise
+    Rotate270,
+    /// mirror left to right
+    FlipX,
+    /// mirror top to bottom
+    FlipY,
+    /// mirror along the x = y diagonal
+    Transpose,
+    /// mirror along the other diagonal
+    AntiTranspose,
+}
+
+impl Symmetry {
+    pub const ALL: [Symmetry; 8] = [
+        Symmetry::Identity,
+        Symmetry::Rotate90,
+        Symmetry::Rotate180,
+        Symmetry::Rotate270,
+        Symmetry::FlipX,
+        Symmetry::FlipY,
+        Symmetry::Transpose,
+        Symmetry::AntiTranspose,
+    ];
+
+    /// Whether the transform swaps the width and height of the board.
+    pub fn swaps_axes(&self) -> bool {
+        matches!(
+            self,
+            Symmetry::Rotate90
+                | Symmetry::Rotate270
+                | Symmetry::Transpose
+                | Symmetry::AntiTranspose
+        )
+    }
+
+    /// Transform a coordinate on a `width` by `height` board.
+    pub fn apply(&self, coord: Coordinate, width: u32, height: u32) -> Coordinate {
+        let (x, y) = (coord.x, coord.y);
+        // largest x and y on the original board
+        let (w, h) = (width as i8 - 1, height as i8 - 1);
+        match self {
+            Symmetry::Identity => Coordinate::new(x, y),
+            Symmetry::Rotate90 => Coordinate::new(h - y, x),
+            Symmetry::Rotate180 => Coordinate::new(w - x, h - y),
+            Symmetry::Rotate270 => Coordinate::new(y, w - x),
+            Symmetry::FlipX => Coordinate::new(w - x, y),
+            Symmetry::FlipY => Coordinate::new(x, h - y),
+            Symmetry::Transpose => Coordinate::new(y, x),
+            Symmetry::AntiTranspose => Coordinate::new(h - y, w - x),
+        }
+    }
+}
+
+impl Game {
+    /// A copy of the game with every body, food and hazard square transformed.
+    pub fn transformed(&self, symmetry: Symmetry) -> Game {
+        let (width, height) = (self.board.width, self.board.height);
+        let map = |coords: &Vec<Coordinate>| {
+            coords
+                .iter()
+                .map(|&c| symmetry.apply(c, width, height))
+                .collect()
+        };
+        let mut snakes = self.board.snakes.clone();
+        for snake in &mut snakes {
+            snake.body = map(&snake.body);
+        }
+        let (width, height) = if symmetry.swaps_axes() {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        Game {
+            board: Board {
+                width,
+                height,
+                snakes,
+                food: map(&self.board.food),
+                hazards: map(&self.board.hazards),
+            },
+            you_id: self.you_id.clone(),
+            turn: self.turn,
+        }
+    }
+}
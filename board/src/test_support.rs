@@ -0,0 +1,34 @@
+//! Fixtures shared by the tests of the workspace, enabled by the `test-support` feature.
+use crate::{
+    useful_board::{Board, Game, Snake},
+    Coordinate,
+};
+
+/// Coordinates from `(x, y)` pairs.
+pub fn coords(points: &[(i8, i8)]) -> Vec<Coordinate> {
+    points.iter().map(|&(x, y)| Coordinate::new(x, y)).collect()
+}
+
+/// A snake with 90 health and the given body, head first.
+pub fn snake(id: &str, body: &[(i8, i8)]) -> Snake {
+    Snake {
+        id: id.to_string(),
+        body: coords(body),
+        health: 90,
+    }
+}
+
+/// Turn 0 of a game on an 11x11 board without hazards, seen from `you_id`.
+pub fn game(you_id: &str, snakes: Vec<Snake>, food: &[(i8, i8)]) -> Game {
+    Game {
+        board: Board {
+            width: 11,
+            height: 11,
+            snakes,
+            food: coords(food),
+            hazards: vec![],
+        },
+        you_id: you_id.to_string(),
+        turn: 0,
+    }
+}
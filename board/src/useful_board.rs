@@ -14,6 +14,8 @@ pub struct Board {
     pub height: u32,
     pub snakes: Vec<Snake>,
     pub food: Vec<Coordinate>,
+    #[serde(default)]
+    pub hazards: Vec<Coordinate>,
 }
 #[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
 pub struct Snake {
@@ -22,6 +24,11 @@ pub struct Snake {
     pub health: u8,
 }
 impl Snake {
+    /// Whether the snake is still in the game.
+    pub fn is_alive(&self) -> bool {
+        self.health > 0 && !self.body.is_empty()
+    }
+
     pub fn square_moves(coord: Coordinate) -> Vec<Coordinate> {
         vec![
             Coordinate::new(0, 1) + coord,
@@ -0,0 +1,100 @@
+//! Zobrist style position hashing.
+//!
+//! Instead of a precomputed table of random keys, every key is derived on the fly by running
+//! the parts of the feature through splitmix64 one at a time, which keeps the hash independent
+//! of board size and stable across runs.
+use crate::{useful_board::Game, Coordinate};
+
+const SNAKE_SEGMENT: u64 = 1;
+const SNAKE_HEALTH: u64 = 2;
+const FOOD: u64 = 3;
+const TURN: u64 = 4;
+const DIMENSIONS: u64 = 5;
+const HAZARD: u64 = 6;
+
+// splitmix64 finalizer, see https://prng.di.unimi.it/splitmix64.c
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+// every part is mixed in on its own, so no value of one part can stand in for another
+fn key(kind: u64, a: u64, b: u64, coord: Option<&Coordinate>) -> u64 {
+    let (x, y) = coord.map_or((0, 0), |c| (c.x as u8 as u64, c.y as u8 as u64));
+    [a, b, x, y]
+        .into_iter()
+        .fold(mix(kind), |hash, part| mix(hash ^ part))
+}
+
+impl Game {
+    /// Hash of the board state and turn. The perspective (`you_id`) is not part of the hash.
+    pub fn zobrist_hash(&self) -> u64 {
+        let mut hash = key(TURN, self.turn as u64, 0, None);
+        hash ^= key(
+            DIMENSIONS,
+            self.board.width as u64,
+            self.board.height as u64,
+            None,
+        );
+        for (slot, snake) in self.board.snakes.iter().enumerate() {
+            hash ^= key(SNAKE_HEALTH, slot as u64, snake.health as u64, None);
+            for (idx, segment) in snake.body.iter().enumerate() {
+                hash ^= key(SNAKE_SEGMENT, slot as u64, idx as u64, Some(segment));
+            }
+        }
+        for food in &self.board.food {
+            hash ^= key(FOOD, 0, 0, Some(food));
+        }
+        for hazard in &self.board.hazards {
+            hash ^= key(HAZARD, 0, 0, Some(hazard));
+        }
+        hash
+    }
+
+    /// Slot of `you_id` in the snake list, or `None` if we are no longer on the board.
+    pub fn perspective(&self) -> Option<usize> {
+        self.board.snakes.iter().position(|x| x.id == self.you_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{game, snake};
+
+    use super::*;
+
+    #[test]
+    fn hashes_everything_on_the_board() {
+        let game = Game {
+            turn: 4,
+            ..game("a", vec![snake("a", &[(1, 1), (1, 0)])], &[(3, 3)])
+        };
+        let hash = game.zobrist_hash();
+        let mut wider = game.clone();
+        wider.board.width = 19;
+        let mut hazardous = game.clone();
+        hazardous.board.hazards.push(Coordinate::new(5, 5));
+        let mut later = game.clone();
+        later.turn += 1 << 16;
+        let mut perspective = game.clone();
+        perspective.you_id = "b".to_string();
+        for other in [wider, hazardous, later] {
+            assert_ne!(other.zobrist_hash(), hash);
+        }
+        assert_eq!(perspective.zobrist_hash(), hash);
+    }
+
+    #[test]
+    fn keys_of_different_kinds_differ() {
+        // turns past 2^16 used to spill into the bits of the kind
+        for turn in [1 << 16, 3 << 16, 1 << 24] {
+            for kind in [SNAKE_SEGMENT, SNAKE_HEALTH, FOOD, DIMENSIONS, HAZARD] {
+                for (a, b) in [(0, 0), (1, 0), (0, 1)] {
+                    assert_ne!(key(TURN, turn, 0, None), key(kind, a, b, None));
+                }
+            }
+        }
+    }
+}
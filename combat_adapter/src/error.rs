@@ -0,0 +1,51 @@
+use thiserror::Error;
+
+/// Everything that can go wrong while importing a dump.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// the database couldn't be opened or queried
+    #[error("sqlite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    /// a game's record couldn't be read from the `games` table
+    #[error("game {game_id}: unable to read record: {source}")]
+    Read {
+        game_id: String,
+        source: rusqlite::Error,
+    },
+    /// a game's record isn't valid zstd
+    #[error("game {game_id}: unable to decompress record: {source}")]
+    Decompress {
+        game_id: String,
+        source: std::io::Error,
+    },
+    /// a game's record isn't the expected JSON
+    #[error("game {game_id}: unable to parse record: {source}")]
+    Parse {
+        game_id: String,
+        source: serde_json::Error,
+    },
+    /// an exported game file couldn't be read
+    #[error("{path}: unable to read file: {source}")]
+    File {
+        path: String,
+        source: std::io::Error,
+    },
+    /// an exported game file isn't in the expected format
+    #[error("{path}: unable to parse file: {source}")]
+    ParseFile {
+        path: String,
+        source: serde_json::Error,
+    },
+}
+
+impl Error {
+    /// The game the error belongs to, if it only affects a single record.
+    pub fn game_id(&self) -> Option<&str> {
+        match self {
+            Error::Sqlite(_) | Error::File { .. } | Error::ParseFile { .. } => None,
+            Error::Read { game_id, .. }
+            | Error::Decompress { game_id, .. }
+            | Error::Parse { game_id, .. } => Some(game_id),
+        }
+    }
+}
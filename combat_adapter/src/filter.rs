@@ -0,0 +1,160 @@
+use std::ops::RangeInclusive;
+
+use board::incoming_board::Request;
+use serde::{Deserialize, Serialize};
+
+/// Which games, and which turns of those games, to extract from a dump.
+///
+/// Built up from [`Filter::new`], which accepts everything:
+/// ```
+/// # use combat_adapter::Filter;
+/// // duels on 11x11 standard, skipping the opening
+/// let filter = Filter::new()
+///     .snakes(2..=2)
+///     .board_size(11, 11)
+///     .ruleset("standard")
+///     .min_turns(21)
+///     .skip_turns(5);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Filter {
+    /// fewest turns a game can last
+    pub min_turns: Option<usize>,
+    /// most turns a game can last
+    pub max_turns: Option<usize>,
+    pub ruleset: Option<String>,
+    pub map: Option<String>,
+    /// width and height of the board
+    pub board_size: Option<(u32, u32)>,
+    pub min_snakes: Option<usize>,
+    pub max_snakes: Option<usize>,
+    /// only games with at least one of these snakes, by id
+    pub snake_ids: Vec<String>,
+    /// only games with at least one of these snakes, by name
+    pub snake_names: Vec<String>,
+    /// first turn of each game to extract
+    pub first_turn: u32,
+    /// last turn of each game to extract
+    pub last_turn: Option<u32>,
+}
+
+impl Filter {
+    pub fn new() -> Filter {
+        Filter::default()
+    }
+
+    pub fn min_turns(mut self, turns: usize) -> Filter {
+        self.min_turns = Some(turns);
+        self
+    }
+
+    pub fn max_turns(mut self, turns: usize) -> Filter {
+        self.max_turns = Some(turns);
+        self
+    }
+
+    pub fn ruleset(mut self, name: &str) -> Filter {
+        self.ruleset = Some(name.to_string());
+        self
+    }
+
+    pub fn map(mut self, name: &str) -> Filter {
+        self.map = Some(name.to_string());
+        self
+    }
+
+    pub fn board_size(mut self, width: u32, height: u32) -> Filter {
+        self.board_size = Some((width, height));
+        self
+    }
+
+    /// Number of snakes that started the game.
+    pub fn snakes(mut self, count: RangeInclusive<usize>) -> Filter {
+        self.min_snakes = Some(*count.start());
+        self.max_snakes = Some(*count.end());
+        self
+    }
+
+    pub fn snake_ids<S: ToString>(mut self, ids: &[S]) -> Filter {
+        self.snake_ids = ids.iter().map(|x| x.to_string()).collect();
+        self
+    }
+
+    pub fn snake_names<S: ToString>(mut self, names: &[S]) -> Filter {
+        self.snake_names = names.iter().map(|x| x.to_string()).collect();
+        self
+    }
+
+    /// Skip the first `turns` turns of every game.
+    pub fn skip_turns(mut self, turns: u32) -> Filter {
+        self.first_turn = turns;
+        self
+    }
+
+    /// Only extract turns `first..=last` of every game.
+    pub fn turn_window(mut self, turns: RangeInclusive<u32>) -> Filter {
+        self.first_turn = *turns.start();
+        self.last_turn = Some(*turns.end());
+        self
+    }
+
+    /// Check the participants of a game, before its record has been read.
+    pub fn accepts_participants(&self, snake_ids: &[String]) -> bool {
+        self.min_snakes.is_none_or(|x| snake_ids.len() >= x)
+            && self.max_snakes.is_none_or(|x| snake_ids.len() <= x)
+            && (self.snake_ids.is_empty() || snake_ids.iter().any(|x| self.snake_ids.contains(x)))
+    }
+
+    /// Check a game once its record has been read.
+    pub fn accepts_game(&self, game: &GameSummary) -> bool {
+        self.min_turns.is_none_or(|x| game.turns >= x)
+            && self.max_turns.is_none_or(|x| game.turns <= x)
+            && self
+                .ruleset
+                .as_ref()
+                .is_none_or(|x| game.ruleset.as_deref() == Some(x))
+            && self
+                .map
+                .as_ref()
+                .is_none_or(|x| game.map.as_deref() == Some(x))
+            && self.board_size.is_none_or(|x| game.board_size == x)
+            && (self.snake_names.is_empty()
+                || game
+                    .snake_names
+                    .iter()
+                    .any(|x| self.snake_names.contains(x)))
+    }
+
+    /// Check whether a turn falls inside the turn window.
+    pub fn accepts_turn(&self, turn: u32) -> bool {
+        turn >= self.first_turn && self.last_turn.is_none_or(|x| turn <= x)
+    }
+}
+
+/// What [`Filter::accepts_game`] needs to know about a game, whatever it was imported from.
+#[derive(Clone, Debug, Default)]
+pub struct GameSummary {
+    /// number of recorded turns
+    pub turns: usize,
+    pub ruleset: Option<String>,
+    pub map: Option<String>,
+    pub board_size: (u32, u32),
+    pub snake_names: Vec<String>,
+}
+
+impl GameSummary {
+    /// Summarise a game from its requests.
+    pub fn from_requests(requests: &[&Request]) -> GameSummary {
+        let Some(first) = requests.first() else {
+            return GameSummary::default();
+        };
+        GameSummary {
+            turns: requests.len(),
+            ruleset: first.ruleset_name().map(str::to_string),
+            map: first.map_name().map(str::to_string),
+            board_size: first.board_size(),
+            snake_names: first.snake_names().map(str::to_string).collect(),
+        }
+    }
+}
@@ -0,0 +1,431 @@
+//! Importers for games saved outside of the sqlite dump.
+//!
+//! Two file formats are supported, one game per file:
+//! *   [`Format::EngineExport`] a JSON object with the `Game` and `Frames` returned by the
+//!     official engine's `/games/{id}` and `/games/{id}/frames` endpoints.
+//! *   [`Format::CliOutput`] the JSON lines log written by `battlesnake play --output`: a game
+//!     line, one request per turn and a final result line.
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use board::{
+    incoming_board::Request,
+    useful_board::{Board, Game, Snake},
+    Coordinate,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    extract, Error, Filter, GameResult, GameSummary, Perspective, Positions, SkipErrors, DB,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    /// a combat-reptile style sqlite dump
+    #[default]
+    Sqlite,
+    /// official engine game exports
+    EngineExport,
+    /// `battlesnake play --output` logs
+    CliOutput,
+}
+
+/// Stream the games at `path` in any format, one game at a time.
+///
+/// For the file formats `path` can be a single file or a directory of them.
+pub fn stream(
+    path: &str,
+    format: Format,
+    filter: Filter,
+    perspective: Perspective,
+) -> Result<Box<dyn Iterator<Item = Result<Positions, Error>>>, Error> {
+    Ok(match format {
+        Format::Sqlite => Box::new(DB::stream(path.to_string(), filter, perspective)?),
+        format => Box::new(FileStream::new(
+            Path::new(path),
+            format,
+            filter,
+            perspective,
+        )?),
+    })
+}
+
+/// Iterator over exported game files, yielding the positions of one game at a time.
+pub struct FileStream {
+    format: Format,
+    filter: Filter,
+    perspective: Perspective,
+    pending: std::vec::IntoIter<PathBuf>,
+}
+
+impl FileStream {
+    /// Read a single file, or every file in a directory in name order.
+    pub fn new(
+        path: &Path,
+        format: Format,
+        filter: Filter,
+        perspective: Perspective,
+    ) -> Result<FileStream, Error> {
+        let file_error = |source| Error::File {
+            path: path.display().to_string(),
+            source,
+        };
+        let mut files = vec![];
+        if path.is_dir() {
+            for entry in fs::read_dir(path).map_err(file_error)? {
+                let entry = entry.map_err(file_error)?;
+                if entry.path().is_file() {
+                    files.push(entry.path());
+                }
+            }
+            files.sort();
+        } else {
+            files.push(path.to_path_buf());
+        }
+        Ok(FileStream {
+            format,
+            filter,
+            perspective,
+            pending: files.into_iter(),
+        })
+    }
+
+    /// Collect bad files in a [`SkipReport`](crate::SkipReport) instead of yielding them as
+    /// errors.
+    pub fn skip_errors(self) -> SkipErrors<FileStream> {
+        SkipErrors::new(self)
+    }
+}
+
+impl Iterator for FileStream {
+    type Item = Result<Positions, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for path in self.pending.by_ref() {
+            let game = match self.format {
+                Format::EngineExport => read_engine_export(&path),
+                Format::CliOutput => read_cli_output(&path),
+                Format::Sqlite => unreachable!("sqlite dumps are read by DB::stream"),
+            };
+            let (frames, summary, winner) = match game {
+                Ok(game) => game,
+                Err(err) => return Some(Err(err)),
+            };
+            // the snakes on the first frame are everyone who took part
+            let participants: Vec<_> = frames
+                .first()
+                .map(|x| x.board.snakes.iter().map(|x| x.id.clone()).collect())
+                .unwrap_or_default();
+            if !self.filter.accepts_participants(&participants) {
+                continue;
+            }
+            let result = GameResult::new(&frames, winner);
+            if let Some(positions) =
+                extract(&frames, &summary, &result, &self.filter, self.perspective)
+            {
+                return Some(Ok(positions));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.pending.len()))
+    }
+}
+
+/// The frames of a game, its summary for filtering, and its winner.
+type ImportedGame = (Vec<Game>, GameSummary, Option<String>);
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EngineExport {
+    game: EngineGame,
+    frames: Vec<EngineFrame>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EngineGame {
+    width: u32,
+    height: u32,
+    ruleset: EngineRuleset,
+    #[serde(default)]
+    map: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct EngineRuleset {
+    #[serde(alias = "Name")]
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EngineFrame {
+    turn: u32,
+    snakes: Vec<EngineSnake>,
+    // the engine writes empty lists as null
+    food: Option<Vec<EngineCoordinate>>,
+    hazards: Option<Vec<EngineCoordinate>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EngineSnake {
+    #[serde(rename = "ID")]
+    id: String,
+    #[serde(default)]
+    name: String,
+    body: Vec<EngineCoordinate>,
+    health: u8,
+    /// set once the snake has been eliminated
+    death: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+struct EngineCoordinate {
+    #[serde(rename = "X")]
+    x: i8,
+    #[serde(rename = "Y")]
+    y: i8,
+}
+
+impl From<EngineCoordinate> for Coordinate {
+    fn from(value: EngineCoordinate) -> Self {
+        Coordinate::new(value.x, value.y)
+    }
+}
+
+fn coordinates(coords: &Option<Vec<EngineCoordinate>>) -> Vec<Coordinate> {
+    coords.iter().flatten().map(|&x| x.into()).collect()
+}
+
+/// Read an official engine export.
+///
+/// Eliminated snakes are removed from the board like they are in move requests, and every
+/// position is seen from the first snake in the game.
+fn read_engine_export(path: &Path) -> Result<ImportedGame, Error> {
+    let file = File::open(path).map_err(|source| Error::File {
+        path: path.display().to_string(),
+        source,
+    })?;
+    let export: EngineExport =
+        serde_json::from_reader(BufReader::new(file)).map_err(|source| Error::ParseFile {
+            path: path.display().to_string(),
+            source,
+        })?;
+    let you_id = export
+        .frames
+        .first()
+        .and_then(|x| x.snakes.first())
+        .map(|x| x.id.clone())
+        .unwrap_or_default();
+    let frames: Vec<Game> = export
+        .frames
+        .iter()
+        .map(|frame| Game {
+            board: Board {
+                width: export.game.width,
+                height: export.game.height,
+                snakes: frame
+                    .snakes
+                    .iter()
+                    .filter(|x| x.death.is_none())
+                    .map(|x| Snake {
+                        id: x.id.clone(),
+                        body: x.body.iter().map(|&x| x.into()).collect(),
+                        health: x.health,
+                    })
+                    .collect(),
+                food: coordinates(&frame.food),
+                hazards: coordinates(&frame.hazards),
+            },
+            you_id: you_id.clone(),
+            turn: frame.turn,
+        })
+        .collect();
+    // the winner is the only snake left standing at the end
+    let winner = match &frames.last().map(|x| &x.board.snakes[..]) {
+        Some([winner]) => Some(winner.id.clone()),
+        _ => None,
+    };
+    let summary = GameSummary {
+        turns: frames.len(),
+        ruleset: Some(export.game.ruleset.name),
+        map: export.game.map,
+        board_size: (export.game.width, export.game.height),
+        snake_names: export
+            .frames
+            .first()
+            .map(|x| x.snakes.iter().map(|x| x.name.clone()).collect())
+            .unwrap_or_default(),
+    };
+    Ok((frames, summary, winner))
+}
+
+/// The last line of a `battlesnake play --output` log.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CliResult {
+    winner_id: String,
+    is_draw: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CliLine {
+    Turn(Box<Request>),
+    Result(CliResult),
+}
+
+/// Read a `battlesnake play --output` log.
+///
+/// The first line describes the game and is skipped, the turns are the requests sent to the
+/// first snake.
+fn read_cli_output(path: &Path) -> Result<ImportedGame, Error> {
+    let file_error = |source| Error::File {
+        path: path.display().to_string(),
+        source,
+    };
+    let file = File::open(path).map_err(file_error)?;
+    let mut requests = vec![];
+    let mut winner = None;
+    for line in BufReader::new(file).lines().skip(1) {
+        let line = line.map_err(file_error)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line = serde_json::from_str(&line).map_err(|source| Error::ParseFile {
+            path: path.display().to_string(),
+            source,
+        })?;
+        match line {
+            CliLine::Turn(request) => requests.push(*request),
+            CliLine::Result(result) => {
+                if !result.is_draw && !result.winner_id.is_empty() {
+                    winner = Some(result.winner_id);
+                }
+            }
+        }
+    }
+    let summary = GameSummary::from_requests(&requests.iter().collect::<Vec<_>>());
+    let frames = requests.iter().map(|x| x.into_usable()).collect();
+    Ok((frames, summary, winner))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{tests::TempFile, Outcome};
+
+    fn write(name: &str, contents: &str) -> TempFile {
+        let file = TempFile::new(name);
+        fs::write(&*file, contents).unwrap();
+        file
+    }
+
+    #[test]
+    fn reads_engine_exports() {
+        let snake = |id: &str, x: i8, death: bool| {
+            json!({
+                "ID": id,
+                "Name": format!("snake {id}"),
+                "Body": [{"X": x, "Y": 2}, {"X": x, "Y": 1}],
+                "Health": 100,
+                "Death": if death { json!({"Cause": "wall-collision", "Turn": 3}) } else { json!(null) },
+            })
+        };
+        let frames: Vec<_> = (0..4)
+            .map(|turn| {
+                json!({
+                    "Turn": turn,
+                    "Snakes": [snake("a", 1, false), snake("b", 5, turn == 3)],
+                    "Food": if turn == 0 { json!([{"X": 3, "Y": 3}]) } else { json!(null) },
+                    "Hazards": [],
+                })
+            })
+            .collect();
+        let export = json!({
+            "Game": {"ID": "g1", "Width": 7, "Height": 7, "Ruleset": {"name": "standard"}, "Map": "standard"},
+            "Frames": frames,
+        });
+        let path = write("engine.json", &export.to_string());
+        let positions: Vec<_> = FileStream::new(
+            &path,
+            Format::EngineExport,
+            Filter::new(),
+            Perspective::Every,
+        )
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap()
+        .concat();
+        assert_eq!(positions.len(), 3 * 2);
+        assert_eq!(positions[0].0.board.width, 7);
+        assert_eq!(positions[0].0.board.food, vec![Coordinate::new(3, 3)]);
+        assert_eq!(
+            positions[0].0.board.snakes[1].body[0],
+            Coordinate::new(5, 2)
+        );
+        assert_eq!(
+            (positions[0].0.you_id.as_str(), positions[0].1.outcome),
+            ("a", Outcome::Win)
+        );
+        assert_eq!(
+            (positions[1].0.you_id.as_str(), positions[1].1.outcome),
+            ("b", Outcome::Loss)
+        );
+        assert_eq!(positions[0].1.turns_until_end, 3);
+
+        let filtered = FileStream::new(
+            &path,
+            Format::EngineExport,
+            Filter::new().board_size(11, 11),
+            Perspective::Recorded,
+        )
+        .unwrap()
+        .count();
+        assert_eq!(filtered, 0);
+    }
+
+    #[test]
+    fn reads_cli_output() {
+        let snake = |id: &str, x: i8| json!({"id": id, "name": format!("snake {id}"), "health": 90, "body": [{"x": x, "y": 1}]});
+        let game = json!({"id": "g1", "ruleset": {"name": "standard", "version": "cli"}, "map": "standard", "timeout": 500, "source": ""});
+        let mut lines = vec![game.to_string()];
+        for turn in 0..5 {
+            lines.push(
+                json!({
+                    "game": game,
+                    "turn": turn,
+                    "you": snake("a", 1),
+                    "board": {"width": 11, "height": 11, "food": [], "hazards": [], "snakes": [snake("a", 1), snake("b", 9)]},
+                })
+                .to_string(),
+            );
+        }
+        lines.push(json!({"winnerId": "", "winnerName": "", "isDraw": true}).to_string());
+        let path = write("cli.jsonl", &lines.join("\n"));
+        let positions = FileStream::new(
+            &path,
+            Format::CliOutput,
+            Filter::new().ruleset("standard"),
+            Perspective::Recorded,
+        )
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap();
+        assert_eq!(positions.len(), 4);
+        assert!(positions
+            .iter()
+            .all(|(x, y)| x.you_id == "a" && y.outcome == Outcome::Draw));
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::{HashMap, HashSet};
+
+use board::useful_board::Game;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    Win,
+    Loss,
+    Draw,
+}
+
+impl Outcome {
+    /// The result as a win probability target: 1 for a win, 0 for a loss, 0.5 for a draw.
+    pub fn result(&self) -> f64 {
+        match self {
+            Outcome::Win => 1.0,
+            Outcome::Loss => 0.0,
+            Outcome::Draw => 0.5,
+        }
+    }
+}
+
+/// How the game ended for the snake a position is seen from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Label {
+    pub outcome: Outcome,
+    /// finishing position, 1 for the winner. Snakes eliminated on the same turn share a placement.
+    pub placement: u32,
+    /// number of turns from this position to the last turn of the game
+    pub turns_until_end: u32,
+}
+
+/// Everything needed to label the positions of one game.
+pub struct GameResult {
+    winner: Option<String>,
+    /// last turn each snake was on the board
+    last_seen: HashMap<String, u32>,
+    /// snakes still on the board when the frames stop, without a record of their own, so they
+    /// may have outlasted the snakes seen going out on the same turn
+    unknown: HashSet<String>,
+    final_turn: u32,
+}
+
+impl GameResult {
+    /// Work out when each snake was eliminated from the frames of a game.
+    ///
+    /// The frames must run up to the end of the game. `winner` is `None` for draws and games
+    /// without a recorded winner.
+    pub fn new(frames: &[Game], winner: Option<String>) -> GameResult {
+        let mut result = GameResult::from_records(&[frames], winner);
+        result.unknown.clear();
+        result
+    }
+
+    /// Work out when each snake was eliminated from the records of a game, each holding the
+    /// frames sent to one snake while it was alive.
+    ///
+    /// A record stops when its snake is eliminated, so snakes still on the board at the end of
+    /// every record may have outlasted the recorded ones.
+    pub fn from_records(records: &[impl AsRef<[Game]>], winner: Option<String>) -> GameResult {
+        let mut last_seen = HashMap::new();
+        let mut recorded = HashSet::new();
+        let mut final_turn = 0;
+        for frame in records.iter().flat_map(|x| x.as_ref()) {
+            recorded.insert(frame.you_id.clone());
+            final_turn = final_turn.max(frame.turn);
+            for snake in &frame.board.snakes {
+                let seen = last_seen.entry(snake.id.clone()).or_insert(frame.turn);
+                *seen = (*seen).max(frame.turn);
+            }
+        }
+        let unknown = last_seen
+            .iter()
+            .filter(|(id, &x)| x == final_turn && !recorded.contains(*id))
+            .map(|(id, _)| id.clone())
+            .collect();
+        GameResult {
+            winner,
+            last_seen,
+            unknown,
+            final_turn,
+        }
+    }
+
+    /// Label a position on `turn` from the perspective of `snake_id`.
+    pub fn label(&self, snake_id: &str, turn: u32) -> Label {
+        let seen = |id: &str| self.last_seen.get(id).copied().unwrap_or(0);
+        let is_winner = |id: &str| self.winner.as_deref() == Some(id);
+        // won, outlasted us, or went out on our turn and may have outlasted us
+        let ahead = |id: &str| {
+            id != snake_id
+                && (is_winner(id)
+                    || seen(id) > seen(snake_id)
+                    || (seen(id) == seen(snake_id) && self.unknown.contains(id)))
+        };
+        let outcome = if is_winner(snake_id) {
+            Outcome::Win
+        } else if self.winner.is_none() && !self.last_seen.keys().any(|x| ahead(x)) {
+            // nobody won and nobody outlasted us, so we went out together with the last snakes
+            Outcome::Draw
+        } else {
+            Outcome::Loss
+        };
+        let placement = if is_winner(snake_id) {
+            1
+        } else {
+            // everyone who won or outlasted us finished ahead
+            1 + self.last_seen.keys().filter(|x| ahead(x)).count() as u32
+        };
+        Label {
+            outcome,
+            placement,
+            turns_until_end: self.final_turn.saturating_sub(turn),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use board::test_support::{game, snake};
+
+    use super::*;
+
+    // one frame per turn, with the given snakes still on the board
+    fn frames(alive: &[&[&str]]) -> Vec<Game> {
+        alive
+            .iter()
+            .enumerate()
+            .map(|(turn, ids)| Game {
+                turn: turn as u32,
+                ..game(
+                    ids[0],
+                    ids.iter().map(|x| snake(x, &[(0, 0)])).collect(),
+                    &[],
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn places_snakes_by_elimination_turn() {
+        let frames = frames(&[&["a", "b", "c", "d"], &["a", "b", "c"], &["a", "b"], &["a"]]);
+        let result = GameResult::new(&frames, Some("a".to_string()));
+        let label = |id| result.label(id, 1);
+        assert_eq!(
+            (label("a").outcome, label("a").placement),
+            (Outcome::Win, 1)
+        );
+        assert_eq!(
+            (label("b").outcome, label("b").placement),
+            (Outcome::Loss, 2)
+        );
+        assert_eq!(
+            (label("c").outcome, label("c").placement),
+            (Outcome::Loss, 3)
+        );
+        assert_eq!(
+            (label("d").outcome, label("d").placement),
+            (Outcome::Loss, 4)
+        );
+        assert_eq!(label("a").turns_until_end, 2);
+    }
+
+    #[test]
+    fn simultaneous_elimination_is_a_draw() {
+        let frames = frames(&[&["a", "b", "c"], &["a", "b"]]);
+        let result = GameResult::new(&frames, None);
+        assert_eq!(result.label("a", 0).outcome, Outcome::Draw);
+        assert_eq!(result.label("b", 0).placement, 1);
+        assert_eq!(result.label("c", 0).outcome, Outcome::Loss);
+        assert_eq!(result.label("c", 0).placement, 3);
+    }
+
+    #[test]
+    fn snakes_outliving_the_records_are_not_a_draw() {
+        // only a's record, which stops when a is eliminated with b still on the board
+        let a = frames(&[&["a", "b"], &["a", "b"]]);
+        let result = GameResult::from_records(&[&a], None);
+        assert_eq!(result.label("a", 0).outcome, Outcome::Loss);
+        assert_eq!(result.label("a", 0).placement, 2);
+
+        // b's record shows b going out on the same turn
+        let b = frames(&[&["b", "a"], &["b", "a"]]);
+        let result = GameResult::from_records(&[&a, &b], None);
+        assert_eq!(result.label("a", 0).outcome, Outcome::Draw);
+        assert_eq!(result.label("a", 0).placement, 1);
+
+        // or outlasting a
+        let b = frames(&[&["b", "a"], &["b", "a"], &["b"]]);
+        let result = GameResult::from_records(&[&a, &b], None);
+        assert_eq!(result.label("a", 0).outcome, Outcome::Loss);
+        assert_eq!(result.label("b", 0).outcome, Outcome::Draw);
+        assert_eq!(result.label("b", 0).placement, 1);
+    }
+}
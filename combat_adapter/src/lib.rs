@@ -1,18 +1,54 @@
-use std::io::Read;
+mod error;
+mod filter;
+mod import;
+mod label;
+
+use std::{collections::HashMap, io::Read};
 
 use board::{incoming_board::Request, useful_board::Game};
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
 use zstd::Decoder;
 
+pub use error::Error;
+pub use filter::{Filter, GameSummary};
+pub use import::{stream, FileStream, Format};
+pub use label::{GameResult, Label, Outcome};
+
+/// The labelled positions of one game.
+pub type Positions = Vec<(Game, Label)>;
+
+/// Which snakes each extracted position is seen from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Perspective {
+    /// only the snake whose record it is
+    #[default]
+    Recorded,
+    /// every living snake, once each
+    Every,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct DB {
-    pub positions: Vec<(Game, String)>,
+    pub positions: Positions,
 }
-struct ParticipantsEntry {
+/// The participants of one game.
+struct Participants {
     game_id: String,
-    snake_id: String,
-    winner: u8,
+    snakes: Vec<String>,
+    /// the snakes marked as having won
+    winners: Vec<String>,
+}
+
+impl Participants {
+    /// The winner of the game, `None` for draws and games without exactly one recorded winner.
+    fn winner(&self) -> Option<String> {
+        match &self.winners[..] {
+            [winner] => Some(winner.clone()),
+            _ => None,
+        }
+    }
 }
 #[derive(Deserialize, Debug, Clone)]
 struct Record {
@@ -24,86 +60,588 @@ struct IRecord {
 }
 
 impl DB {
-    pub fn new(path: String, num_snakes: usize) -> Self {
-        assert!(num_snakes > 1);
+    /// Load every position of every matching game into memory, stopping at the first bad
+    /// record.
+    pub fn new(path: String, filter: Filter) -> Result<Self, Error> {
+        let positions =
+            DB::stream(path, filter, Perspective::Recorded)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(DB {
+            positions: positions.into_iter().flatten().collect(),
+        })
+    }
 
-        // open a connection to the database and grab all the game, snake, and winner values for each row.
-        let conn = Connection::open(path).unwrap();
-        let mut stmt = conn
-            .prepare("SELECT gid, sid, won FROM participants")
-            .unwrap();
-        let person_iter = stmt
-            .query_map([], |row| {
-                Ok(ParticipantsEntry {
-                    game_id: row.get(0)?,
-                    snake_id: row.get(1)?,
-                    winner: row.get(2)?,
-                })
-            })
-            .unwrap();
+    /// Load every position of every matching game into memory, skipping bad records.
+    pub fn new_skipping(path: String, filter: Filter) -> Result<(Self, SkipReport), Error> {
+        let mut games = DB::stream(path, filter, Perspective::Recorded)?.skip_errors();
+        let positions = games.by_ref().flatten().collect();
+        Ok((DB { positions }, games.report))
+    }
 
-        //Find all the game ids and their respective winners that match the given size requirement
-        let mut thing = vec![];
-        let mut num_snake_counter = 0;
-        let mut current_game_id = String::new();
-        let mut current_winner_id = String::new();
-        for x in person_iter {
-            let x = x.unwrap();
-            // if the current row is the winner, update the winner.
-            if x.winner == 1 {
-                current_winner_id = x.snake_id;
+    /// Stream the positions of the games accepted by `filter`, one game at a time.
+    pub fn stream(
+        path: String,
+        filter: Filter,
+        perspective: Perspective,
+    ) -> Result<GameStream, Error> {
+        // open a connection to the database and find the games with matching participants
+        let conn = Connection::open(path)?;
+        let winners: HashMap<String, Option<String>> = participants(&conn)?
+            .into_iter()
+            .filter(|x| filter.accepts_participants(&x.snakes))
+            .map(|x| (x.game_id.clone(), x.winner()))
+            .collect();
+        // Using the found game ids from above, find the rows of the matching games without
+        // reading any of the records yet. A game may have a record for each of its snakes.
+        let mut stmt = conn.prepare("SELECT rowid, gid FROM games")?;
+        let games_iter = stmt.query_map([], |row| {
+            Ok((row.get::<usize, i64>(0)?, row.get::<usize, String>(1)?))
+        })?;
+        let mut pending: Vec<(Vec<i64>, String, Option<String>)> = vec![];
+        let mut index = HashMap::new();
+        for row in games_iter {
+            let (rowid, game_id) = row?;
+            if let Some(winner) = winners.get(&game_id) {
+                let i = *index.entry(game_id.clone()).or_insert_with(|| {
+                    pending.push((vec![], game_id, winner.clone()));
+                    pending.len() - 1
+                });
+                pending[i].0.push(rowid);
             }
-            // condition for the first entry, detected by current_game_id being empty.
-            if current_game_id.is_empty() {
-                current_game_id = x.game_id;
-                num_snake_counter = 1;
-                continue;
+        }
+        drop(stmt);
+
+        Ok(GameStream {
+            conn,
+            filter,
+            perspective,
+            pending: pending.into_iter(),
+        })
+    }
+}
+
+// grab all the game, snake, and winner values, grouped by game.
+fn participants(conn: &Connection) -> Result<Vec<Participants>, Error> {
+    let mut stmt = conn.prepare("SELECT gid, sid, won FROM participants ORDER BY gid")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<usize, String>(0)?,
+            row.get::<usize, String>(1)?,
+            row.get::<usize, u8>(2)?,
+        ))
+    })?;
+    let mut games: Vec<Participants> = vec![];
+    for row in rows {
+        let (game_id, snake_id, won) = row?;
+        // the rows are ordered by game, so a new id means the previous game is complete
+        if games.last().is_none_or(|x| x.game_id != game_id) {
+            games.push(Participants {
+                game_id,
+                snakes: vec![],
+                winners: vec![],
+            });
+        }
+        let game = games.last_mut().unwrap();
+        if won == 1 {
+            game.winners.push(snake_id.clone());
+        }
+        game.snakes.push(snake_id);
+    }
+    Ok(games)
+}
+
+/// Label the positions of a game, given every frame including the last one.
+///
+/// Returns `None` if the filter rejects the game. The last frame is never extracted.
+fn extract(
+    frames: &[Game],
+    summary: &GameSummary,
+    result: &GameResult,
+    filter: &Filter,
+    perspective: Perspective,
+) -> Option<Positions> {
+    if frames.is_empty() || !filter.accepts_game(summary) {
+        return None;
+    }
+    let mut out = vec![];
+    for frame in &frames[0..(frames.len() - 1)] {
+        if !filter.accepts_turn(frame.turn) {
+            continue;
+        }
+        match perspective {
+            Perspective::Every => {
+                for snake in frame.board.snakes.iter().filter(|x| x.is_alive()) {
+                    let mut position = frame.clone();
+                    position.you_id = snake.id.clone();
+                    out.push((position, result.label(&snake.id, frame.turn)));
+                }
             }
-            // if it matches the current game id, increase the number of snakes
-            if current_game_id == x.game_id {
-                num_snake_counter += 1;
-            } else {
-                // if it doesnt, then we have moved onto a new game
-                if num_snake_counter == num_snakes {
-                    // store the game id and the winner id.
-                    thing.push((current_game_id, current_winner_id.clone()));
+            Perspective::Recorded => {
+                if frame.board.snakes.iter().any(|x| x.id == frame.you_id) {
+                    let label = result.label(&frame.you_id, frame.turn);
+                    out.push((frame.clone(), label));
                 }
-                // update the current game id and reset the snake counter
-                current_game_id = x.game_id;
-                num_snake_counter = 1;
             }
         }
-        // Using the found game ids from above, grab the games and convert them
-        // grab all the records and game id's from the games table
-        let mut stmt = conn.prepare("SELECT gid, record FROM games").unwrap();
-        let games_iter = stmt.query_map([], |row| {
-            Ok((
-                row.get::<usize, String>(0).unwrap(),
-                row.get::<usize, Vec<u8>>(1).unwrap(),
-            ))
-        });
-        // convert all the scraped games that match the participant count into the internal representation
-        let mut out = vec![];
-        for row in games_iter.unwrap() {
-            let row = row.unwrap();
-            if let Some(idx) = thing.iter().position(|(id, _)| id == &row.0) {
-                let winner = thing[idx].1.clone();
-                // unwrap the zstd compressed blob
-                let mut x = Decoder::new(&row.1[..]).unwrap();
-                let mut buf = String::new();
-                x.read_to_string(&mut buf).unwrap();
-                // unwrap the JSON into a game record
-                let game: Record = serde_json::from_str(&buf).unwrap();
-                if game.turns.len() <= 20 {
-                    continue;
-                }
-                for frame in &game.turns[0..(game.turns.len() - 1)] {
-                    // convert it into a usable format.
-                    out.push((frame.request.clone().into_usable(), winner.clone()));
-                }
+    }
+    Some(out)
+}
+
+/// Iterator over the matching games of a dump, yielding the positions of one game at a time.
+///
+/// Records are read and decompressed lazily, so only one game is held in memory at once.
+pub struct GameStream {
+    conn: Connection,
+    filter: Filter,
+    perspective: Perspective,
+    /// row ids of the records of each matching game in the `games` table, its game id and its
+    /// winner
+    pending: std::vec::IntoIter<(Vec<i64>, String, Option<String>)>,
+}
+
+impl GameStream {
+    /// Collect bad records in a [`SkipReport`] instead of yielding them as errors.
+    pub fn skip_errors(self) -> SkipErrors<GameStream> {
+        SkipErrors::new(self)
+    }
+
+    // read, decompress and parse the record of a single game
+    fn read_record(&self, rowid: i64, game_id: &str) -> Result<Record, Error> {
+        let blob: Vec<u8> = self
+            .conn
+            .prepare_cached("SELECT record FROM games WHERE rowid = ?1")
+            .and_then(|mut stmt| stmt.query_row([rowid], |row| row.get(0)))
+            .map_err(|source| Error::Read {
+                game_id: game_id.to_string(),
+                source,
+            })?;
+        // unwrap the zstd compressed blob
+        let mut buf = String::new();
+        Decoder::new(&blob[..])
+            .and_then(|mut x| x.read_to_string(&mut buf))
+            .map_err(|source| Error::Decompress {
+                game_id: game_id.to_string(),
+                source,
+            })?;
+        // unwrap the JSON into a game record
+        serde_json::from_str(&buf).map_err(|source| Error::Parse {
+            game_id: game_id.to_string(),
+            source,
+        })
+    }
+}
+
+impl Iterator for GameStream {
+    type Item = Result<Positions, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((rowids, game_id, winner)) = self.pending.next() {
+            let games = match rowids
+                .iter()
+                .map(|&x| self.read_record(x, &game_id))
+                .collect::<Result<Vec<_>, _>>()
+            {
+                Ok(games) => games,
+                Err(err) => return Some(Err(err)),
+            };
+            // the longest record covers every turn of the game
+            let Some(longest) = games.iter().max_by_key(|x| x.turns.len()) else {
+                continue;
+            };
+            let requests: Vec<_> = longest.turns.iter().map(|x| &x.request).collect();
+            let summary = GameSummary::from_requests(&requests);
+            // convert them into a usable format.
+            let records: Vec<Vec<_>> = games
+                .iter()
+                .map(|x| x.turns.iter().map(|x| x.request.into_usable()).collect())
+                .collect();
+            let result = GameResult::from_records(&records, winner);
+            let sources: Vec<_> = match self.perspective {
+                // every record holds the whole board, so one is enough
+                Perspective::Every => records.iter().max_by_key(|x| x.len()).into_iter().collect(),
+                Perspective::Recorded => records.iter().collect(),
+            };
+            let positions: Option<Vec<_>> = sources
+                .into_iter()
+                .map(|x| extract(x, &summary, &result, &self.filter, self.perspective))
+                .collect();
+            if let Some(positions) = positions {
+                return Some(Ok(positions.concat()));
             }
         }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.pending.len()))
+    }
+}
+
+/// The records skipped by [`SkipErrors`].
+#[derive(Debug, Default)]
+pub struct SkipReport {
+    pub skipped: Vec<Error>,
+}
+
+impl SkipReport {
+    pub fn count(&self) -> usize {
+        self.skipped.len()
+    }
+
+    /// Ids of the skipped games.
+    pub fn game_ids(&self) -> impl Iterator<Item = &str> {
+        self.skipped.iter().filter_map(|x| x.game_id())
+    }
+}
 
-        DB { positions: out }
+/// A stream of games that skips bad records instead of yielding errors, leaving it to the
+/// caller to report them from `report`.
+pub struct SkipErrors<I> {
+    inner: I,
+    pub report: SkipReport,
+}
+
+impl<I: Iterator<Item = Result<Positions, Error>>> SkipErrors<I> {
+    pub fn new(inner: I) -> SkipErrors<I> {
+        SkipErrors {
+            inner,
+            report: SkipReport::default(),
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<Positions, Error>>> Iterator for SkipErrors<I> {
+    type Item = Positions;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.next()? {
+                Ok(positions) => return Some(positions),
+                Err(err) => self.report.skipped.push(err),
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.inner.size_hint().1)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        ops::Deref,
+        path::{Path, PathBuf},
+    };
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// A file in the temporary directory, unique to the test process and removed once dropped.
+    pub(crate) struct TempFile(PathBuf);
+
+    impl TempFile {
+        pub(crate) fn new(name: &str) -> TempFile {
+            let path =
+                std::env::temp_dir().join(format!("combat_adapter_{}_{name}", std::process::id()));
+            // a leftover from an aborted run would otherwise be added to
+            let _ = std::fs::remove_file(&path);
+            TempFile(path)
+        }
+    }
+
+    impl Deref for TempFile {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    // a minimal record of a two snake game lasting `turns` turns
+    fn record(turns: u32) -> Vec<u8> {
+        record_with(turns, "standard", 11)
+    }
+
+    // a minimal record of a two snake game on a square board
+    fn record_with(turns: u32, ruleset: &str, size: u32) -> Vec<u8> {
+        record_of("a", &[("a", turns), ("b", turns)], ruleset, size)
+    }
+
+    // a minimal record seen by `you`, lasting until `you` goes out, with each snake on the
+    // board for the given number of turns
+    fn record_of(you: &str, lifetimes: &[(&str, u32)], ruleset: &str, size: u32) -> Vec<u8> {
+        let snake = |id: &str, x: usize| json!({"id": id, "name": format!("snake {id}"), "health": 90, "body": [{"x": 1 + 8 * x, "y": 1}]});
+        let turns = lifetimes.iter().find(|x| x.0 == you).unwrap().1;
+        let you = lifetimes.iter().position(|x| x.0 == you).unwrap();
+        let turns: Vec<_> = (0..turns)
+            .map(|turn| {
+                let snakes: Vec<_> = lifetimes
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, x)| turn < x.1)
+                    .map(|(i, x)| snake(x.0, i))
+                    .collect();
+                json!({"request": {
+                    "game": {"ruleset": {"name": ruleset}, "map": "standard"},
+                    "turn": turn,
+                    "you": snake(lifetimes[you].0, you),
+                    "board": {
+                        "width": size,
+                        "height": size,
+                        "food": [],
+                        "snakes": snakes,
+                    },
+                }})
+            })
+            .collect();
+        let json = serde_json::to_vec(&json!({ "turns": turns })).unwrap();
+        zstd::encode_all(&json[..], 0).unwrap()
+    }
+
+    // build a fixture database from (game id, snake id, won) rows and (game id, record) rows
+    fn fixture(
+        name: &str,
+        participants: &[(&str, &str, u8)],
+        games: &[(&str, Vec<u8>)],
+    ) -> TempFile {
+        let file = TempFile::new(&format!("{name}.sqlite"));
+        let conn = Connection::open(&*file).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE participants (gid TEXT, sid TEXT, won INTEGER);
+             CREATE TABLE games (gid TEXT, record BLOB);",
+        )
+        .unwrap();
+        for (gid, sid, won) in participants {
+            conn.execute(
+                "INSERT INTO participants VALUES (?1, ?2, ?3)",
+                rusqlite::params![gid, sid, won],
+            )
+            .unwrap();
+        }
+        for (gid, record) in games {
+            conn.execute(
+                "INSERT INTO games VALUES (?1, ?2)",
+                rusqlite::params![gid, record],
+            )
+            .unwrap();
+        }
+        file
+    }
+
+    #[test]
+    fn skips_and_reports_bad_records() {
+        let file = fixture(
+            "skip",
+            &[
+                ("g1", "a", 1),
+                ("g1", "b", 0),
+                ("g2", "a", 0),
+                ("g2", "b", 1),
+                ("g3", "a", 1),
+            ],
+            &[("g1", b"not zstd".to_vec()), ("g2", record(25))],
+        );
+        let path = file.display().to_string();
+        assert!(matches!(
+            DB::new(path.clone(), Filter::new().snakes(2..=2)),
+            Err(Error::Decompress { game_id, .. }) if game_id == "g1"
+        ));
+        let (db, report) = DB::new_skipping(path, Filter::new().snakes(2..=2)).unwrap();
+        assert_eq!(db.positions.len(), 24);
+        assert_eq!(report.game_ids().collect::<Vec<_>>(), vec!["g1"]);
+    }
+
+    #[test]
+    fn groups_participants_by_game() {
+        let file = fixture(
+            "grouping",
+            &[
+                ("g2", "a", 0),
+                ("g1", "a", 1),
+                ("g3", "c", 0),
+                ("g2", "b", 0),
+                ("g1", "b", 0),
+                ("g3", "d", 1),
+                ("g4", "a", 1),
+                ("g4", "b", 1),
+            ],
+            &[],
+        );
+        let conn = Connection::open(&*file).unwrap();
+        let games: Vec<_> = participants(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|x| (x.game_id.clone(), x.snakes.len(), x.winner()))
+            .collect();
+        assert_eq!(
+            games,
+            vec![
+                ("g1".to_string(), 2, Some("a".to_string())),
+                // no winner, and not carried over from g1
+                ("g2".to_string(), 2, None),
+                // the final game is still emitted
+                ("g3".to_string(), 2, Some("d".to_string())),
+                // more than one winner counts as none
+                ("g4".to_string(), 2, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn labels_positions_with_the_game_result() {
+        let record_of = |you, lifetimes| record_of(you, lifetimes, "standard", 11);
+        let file = fixture(
+            "labels",
+            &[
+                ("g1", "a", 0),
+                ("g1", "b", 1),
+                ("g2", "a", 0),
+                ("g2", "b", 0),
+                ("g3", "a", 1),
+                ("g3", "b", 0),
+                ("g3", "c", 0),
+                ("g4", "a", 0),
+                ("g4", "b", 0),
+            ],
+            &[
+                ("g1", record(22)),
+                ("g2", record_of("a", &[("a", 30), ("b", 30)])),
+                ("g2", record_of("b", &[("a", 30), ("b", 30)])),
+                ("g3", record(30)),
+                ("g4", record_of("a", &[("a", 20), ("b", 30)])),
+            ],
+        );
+        let path = file.display().to_string();
+        let db = DB::new(path, Filter::new().snakes(2..=2)).unwrap();
+        let labels: Vec<_> = db
+            .positions
+            .iter()
+            .map(|(x, y)| (x.you_id.as_str(), *y))
+            .collect();
+        assert_eq!(labels.len(), 21 + 29 * 2 + 19);
+        let (g1, rest) = labels.split_at(21);
+        let (g2, g4) = rest.split_at(29 * 2);
+        assert!(g1
+            .iter()
+            .all(|(x, y)| *x == "a" && y.outcome == Outcome::Loss && y.placement == 2));
+        assert_eq!(g1[0].1.turns_until_end, 21);
+        assert_eq!(g1[20].1.turns_until_end, 1);
+        // both records end on the same turn
+        assert_eq!(g2.iter().filter(|(x, _)| *x == "b").count(), 29);
+        assert!(g2
+            .iter()
+            .all(|(_, y)| y.outcome == Outcome::Draw && y.placement == 1));
+        // a went out with b still on the board, and there is no record saying when b did
+        assert!(g4
+            .iter()
+            .all(|(x, y)| *x == "a" && y.outcome == Outcome::Loss && y.placement == 2));
+    }
+
+    #[test]
+    fn labels_games_from_every_record() {
+        let record_of = |you, lifetimes| record_of(you, lifetimes, "standard", 11);
+        let lifetimes: &[_] = &[("a", 10), ("b", 20)];
+        let file = fixture(
+            "records",
+            &[("g1", "a", 0), ("g1", "b", 0)],
+            &[
+                ("g1", record_of("a", lifetimes)),
+                ("g1", record_of("b", lifetimes)),
+            ],
+        );
+        let path = file.display().to_string();
+        let games: Vec<_> = DB::stream(path.clone(), Filter::new(), Perspective::Recorded)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        // both records make up a single game
+        assert_eq!(games.len(), 1);
+        let label = |id: &str| {
+            let (_, y) = games[0].iter().find(|(x, _)| x.you_id == id).unwrap();
+            (y.outcome, y.placement, y.turns_until_end)
+        };
+        assert_eq!(label("a"), (Outcome::Loss, 2, 19));
+        assert_eq!(label("b"), (Outcome::Draw, 1, 19));
+
+        // the every perspective sees each turn once, from the longest record
+        let positions: Vec<_> = DB::stream(path, Filter::new(), Perspective::Every)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .concat();
+        assert_eq!(positions.len(), 10 + 19);
+    }
+
+    #[test]
+    fn filters_games_and_turns() {
+        let file = fixture(
+            "filters",
+            &[
+                ("g1", "a", 1),
+                ("g1", "b", 0),
+                ("g2", "a", 1),
+                ("g2", "b", 0),
+                ("g3", "a", 1),
+                ("g3", "b", 0),
+                ("g4", "c", 1),
+                ("g4", "d", 0),
+            ],
+            &[
+                ("g1", record(30)),
+                ("g2", record_with(30, "royale", 11)),
+                ("g3", record_with(30, "standard", 19)),
+                ("g4", record(10)),
+            ],
+        );
+        let path = file.display().to_string();
+        let count = |filter: Filter| DB::new(path.clone(), filter).unwrap().positions.len();
+        assert_eq!(count(Filter::new()), 29 * 3 + 9);
+        assert_eq!(count(Filter::new().min_turns(21)), 29 * 3);
+        assert_eq!(count(Filter::new().max_turns(20)), 9);
+        assert_eq!(count(Filter::new().ruleset("standard")), 29 * 2 + 9);
+        assert_eq!(
+            count(Filter::new().board_size(11, 11).min_turns(21)),
+            29 * 2
+        );
+        assert_eq!(count(Filter::new().snake_ids(&["c"])), 9);
+        assert_eq!(count(Filter::new().snake_names(&["snake a"])), 29 * 3 + 9);
+        assert_eq!(count(Filter::new().snake_names(&["nobody"])), 0);
+        assert_eq!(count(Filter::new().snakes(3..=4)), 0);
+        assert_eq!(count(Filter::new().skip_turns(25)), 4 * 3);
+        assert_eq!(count(Filter::new().turn_window(5..=9)), 5 * 3 + 4);
+    }
+
+    #[test]
+    fn expands_every_perspective() {
+        let file = fixture(
+            "perspectives",
+            &[("g1", "a", 0), ("g1", "b", 1)],
+            &[("g1", record(25))],
+        );
+        let path = file.display().to_string();
+        let positions: Vec<_> = DB::stream(path, Filter::new(), Perspective::Every)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .concat();
+        assert_eq!(positions.len(), 24 * 2);
+        for pair in positions.chunks(2) {
+            assert_eq!(pair[0].0.board, pair[1].0.board);
+            assert_eq!(
+                (pair[0].0.you_id.as_str(), pair[0].1.outcome),
+                ("a", Outcome::Loss)
+            );
+            assert_eq!(
+                (pair[1].0.you_id.as_str(), pair[1].1.outcome),
+                ("b", Outcome::Win)
+            );
+        }
     }
 }
@@ -1,8 +1,9 @@
-use combat_adapter::DB;
+use combat_adapter::{Filter, DB};
 
 fn main() {
     let _db = DB::new(
         "D:\\Moved downlaods and document folders\\Downloads\\combat-reptile_dump_2023-06-27.sqlite".to_string(),
-        2,
-    );
+        Filter::new().snakes(2..=2).min_turns(21),
+    )
+    .unwrap();
 }
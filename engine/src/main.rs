@@ -1,136 +1,114 @@
-// use std::{
-//     fs,
-//     str::FromStr,
-//     sync::{Arc, RwLock},
-//     time::Instant,
-// };
+use std::{fs, sync::Arc, time::Instant};
 
-// use axum::{
-//     routing::{get, post},
-//     Extension, Json, Router,
-// };
+use axum::{
+    extract::State,
+    routing::{get, post},
+    Json, Router,
+};
+use board::{incoming_board::Request, rules::Move};
+use eval::{area_eval::AreaEval, cache::EvalCache, search::Search};
+use serde::{Deserialize, Serialize};
 
-// use board::incoming_board::Request;
-// use eval::area_eval::AreaEval;
-// use serde::{Deserialize, Serialize};
-// use serde_json::json;
+#[derive(Deserialize)]
+struct Config {
+    port: u32,
+    /// weights file written by the tuner
+    weights: String,
+    /// turns searched ahead on every move
+    depth: u32,
+    /// entries in the evaluation cache of each search
+    #[serde(default = "default_cache_size")]
+    cache_size: usize,
+}
 
-// #[derive(Deserialize)]
-// struct Config {
-//     port: u32,
-//     weights: [f64; 5],
-// }
-// #[tokio::main]
-// async fn main() {
-//     let config = fs::read_to_string("config.toml").expect("Unable to read file");
-//     let config: Config = toml::from_str(&config).expect("JSON was not well-formatted");
-//     // build our application with a single route
+fn default_cache_size() -> usize {
+    1 << 16
+}
 
-//     let app = Router::new()
-//         .route("/", get(get_data))
-//         .route("/move", post(get_move))
-//         .route("/start", post(start));
+struct Engine {
+    eval: AreaEval,
+    depth: u32,
+    cache_size: usize,
+}
 
-//     // run it with hyper on localhost:3000
-//     axum::Server::bind(&format!("0.0.0.0:{}", config.port).parse().unwrap())
-//         .serve(app.into_make_service())
-//         .await
-//         .unwrap();
-// }
+#[tokio::main]
+async fn main() {
+    let config = fs::read_to_string("config.toml").expect("Unable to read file");
+    let config: Config = toml::from_str(&config).expect("TOML was not well-formatted");
+    let eval = AreaEval::load(&config.weights)
+        .unwrap_or_else(|e| panic!("Unable to load weights from {}: {e}", config.weights));
+    println!(
+        "loaded weights {:?} from {}",
+        eval.weights(),
+        config.weights
+    );
+    let engine = Arc::new(Engine {
+        eval,
+        depth: config.depth,
+        cache_size: config.cache_size,
+    });
 
-// #[derive(Serialize)]
-// struct SnakeData {
-//     apiversion: String,
-//     author: String,
-//     color: String,
-//     head: String,
-//     tail: String,
-//     version: String,
-// }
-// async fn start() {}
-// async fn get_data() -> Json<SnakeData> {
-//     Json(SnakeData {
-//         apiversion: "1".to_string(),
-//         author: "BrokenKeyboard".to_string(),
-//         color: "#0a571e".to_string(),
-//         head: "dragon".to_string(),
-//         tail: "cosmic-horror".to_string(),
-//         version: "1".to_string(),
-//     })
-// }
+    let app = Router::new()
+        .route("/", get(get_data))
+        .route("/move", post(get_move))
+        .route("/start", post(start))
+        .with_state(engine);
 
-// #[derive(Serialize)]
-// struct OutMove {
-//     #[serde(rename = "move")]
-//     out_move: String,
-//     shout: String,
-// }
-// async fn get_move(Json(request): Json<Request>) -> Json<OutMove> {
-//     let mut usable = request.into_usable();
-//     let t0 = Instant::now();
-//     // let (returned_move, score) = state
-//     //     .write()
-//     //     .unwrap()
-//     //     .search
-//     //     .iterative_deepen(&mut usable, 2);
-//     // let t1 = Instant::now();
-//     // let stats = state.read().unwrap().search.statistics;
-//     // println!("search time {:?}", (t1 - t0));
-//     // let tdiff = (t1 - t0).as_secs_f64();
-//     // let move_string = match returned_move.move_type {
-//     //     MoveType::MoveSquare(square) => {
-//     //         let diff = usable.board.snakes[usable.you_id].body[0].trailing_zeros() as i32
-//     //             - square.trailing_zeros() as i32;
-//     //         match diff {
-//     //             -1 => "right".to_string(),
-//     //             1 => "left".to_string(),
-//     //             -11 => "up".to_string(),
-//     //             11 => "down".to_string(),
-//     //             _ => {
-//     //                 panic!("Invalid move");
-//     //             }
-//     //         }
-//     //     }
-//     //     MoveType::Death => String::from_str("up").unwrap(),
-//     // };
-//     // println!("Score: {}", score);
-//     // println!("Move direction : {}", move_string);
-//     // println!(
-//     //     "NPS: {}, TT Hits: {}, Nodes: {}",
-//     //     stats.node_count as f64 / tdiff,
-//     //     stats.tt_hits,
-//     //     stats.node_count
-//     // );
-//     Json(OutMove {
-//         out_move: "up".to_string(),
-//         shout: String::from_str("hello").unwrap(),
-//     })
-// }
-//! Run with
-//!
-//! ```not_rust
-//! cargo run -p example-hello-world
-//! ```
+    axum::Server::bind(&format!("0.0.0.0:{}", config.port).parse().unwrap())
+        .serve(app.into_make_service())
+        .await
+        .unwrap();
+}
 
-// use axum::{response::Html, routing::get, Router};
-// use std::net::SocketAddr;
+#[derive(Serialize)]
+struct SnakeData {
+    apiversion: String,
+    author: String,
+    color: String,
+    head: String,
+    tail: String,
+    version: String,
+}
+async fn start() {}
+async fn get_data() -> Json<SnakeData> {
+    Json(SnakeData {
+        apiversion: "1".to_string(),
+        author: "BrokenKeyboard".to_string(),
+        color: "#0a571e".to_string(),
+        head: "dragon".to_string(),
+        tail: "cosmic-horror".to_string(),
+        version: "1".to_string(),
+    })
+}
 
-// #[tokio::main]
-// async fn main() {
-//     // build our application with a route
-//     let app = Router::new().route("/", get(handler));
-
-//     // run it
-//     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
-//     println!("listening on {}", addr);
-//     axum::Server::bind(&addr)
-//         .serve(app.into_make_service())
-//         .await
-//         .unwrap();
-// }
-
-// async fn handler() -> Html<&'static str> {
-//     Html("<h1>Hello, World!</h1>")
-// }
-
-fn main() {}
+#[derive(Serialize)]
+struct OutMove {
+    #[serde(rename = "move")]
+    out_move: Move,
+    shout: String,
+}
+async fn get_move(
+    State(engine): State<Arc<Engine>>,
+    Json(request): Json<Request>,
+) -> Json<OutMove> {
+    let usable = request.into_usable();
+    // the search doesn't yield, so keep it off the threads serving requests
+    let (out_move, score) = tokio::task::spawn_blocking(move || {
+        let t0 = Instant::now();
+        let mut search = Search::new(EvalCache::new(engine.eval.clone(), engine.cache_size));
+        let (out_move, score) = search.best_move(&usable, engine.depth);
+        let stats = search.statistics();
+        println!(
+            "turn {}: {out_move:?} scored {score:.3} in {:?}, {stats}",
+            usable.turn,
+            t0.elapsed()
+        );
+        (out_move, score)
+    })
+    .await
+    .unwrap();
+    Json(OutMove {
+        out_move,
+        shout: format!("{score:.2}"),
+    })
+}
@@ -1,26 +1,69 @@
+use std::{fs, io, path::Path};
+
 use board::{
     useful_board::{Board, Game, Snake},
     Coordinate,
 };
 use nalgebra::SVector;
 use pathfinding::prelude::astar;
+use serde::Deserialize;
 use snake_tuner::{
     activation::{functions::Sigmoid, ActivationFunction},
     evaluation::{evaluations::Linear, Eval},
 };
 
+use crate::terminal::Terminal;
+
+// the part of a weights file the evaluation needs, anything else in it is ignored
+#[derive(Deserialize)]
+struct WeightsFile {
+    weights: [f64; 6],
+}
+
 #[derive(Clone)]
 pub struct AreaEval {
     pub eval: Linear<6, Sigmoid>,
 }
 
 impl AreaEval {
+    /// Names of the values returned by [`AreaEval::label`], in order.
+    pub const FEATURES: [&'static str; 6] = [
+        "length_difference",
+        "distance_to_center",
+        "health_difference",
+        "food_ownership_difference",
+        "square_ownership_difference",
+        "bias",
+    ];
+    /// Bumped whenever [`AreaEval::label`] changes what it computes, so cached features can be
+    /// told apart from fresh ones.
+    pub const FEATURE_VERSION: u32 = 4;
+
     pub fn new(weights: [f64; 6]) -> AreaEval {
         AreaEval {
             eval: Linear::from_weights(SVector::from(weights), Sigmoid),
         }
     }
+    /// Read the `weights` key of a TOML file, such as the ones written by the tuner.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<AreaEval> {
+        Self::from_toml(&fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+    pub fn from_toml(text: &str) -> Result<AreaEval, toml::de::Error> {
+        let file: WeightsFile = toml::from_str(text)?;
+        Ok(AreaEval::new(file.weights))
+    }
+    /// The current weights, in the order of [`AreaEval::FEATURES`].
+    pub fn weights(&self) -> [f64; 6] {
+        // `Linear` keeps its weights private, but a one-hot input reads them back one at a time
+        std::array::from_fn(|i| self.eval.forward(SVector::ith(i, 1.0)))
+    }
     pub fn score(&self, position: &Game) -> f64 {
+        // finished games get a fixed score instead of a probability, as if reached right away;
+        // a search scores them itself, by how far from its root they are
+        if let Some(terminal) = Terminal::detect(position) {
+            return terminal.score(0);
+        }
         let x = Sigmoid;
         x.evaluate(self.eval.forward(Self::label(position)))
     }
@@ -31,7 +74,7 @@ impl AreaEval {
             .board
             .snakes
             .iter()
-            .position(|x| &x.id == &position.you_id)
+            .position(|x| x.id == position.you_id)
             .unwrap()];
         // them
         let other = &position.board.snakes[position
@@ -57,14 +100,14 @@ impl AreaEval {
             let my_path = astar(
                 &me.body[0],
                 |p| successors(p, &position.board),
-                |p| manhattan(p, &food),
+                |p| manhattan(p, food),
                 |p| p == food,
             );
             // their path to the same food
             let their_path = astar(
                 &other.body[0],
                 |p| successors(p, &position.board),
-                |p| manhattan(p, &food),
+                |p| manhattan(p, food),
                 |p| p == food,
             );
             // my distance to the food
@@ -180,7 +223,6 @@ impl AreaEval {
         ])
     }
 }
-// this is always from the perspective of the first snake (hacky fix, but it works)
 
 // successors for a given coordinate
 fn successors(coord: &Coordinate, board: &Board) -> Vec<(Coordinate, i32)> {
@@ -207,3 +249,21 @@ fn successors(coord: &Coordinate, board: &Board) -> Vec<(Coordinate, i32)> {
 fn manhattan(c1: &Coordinate, c2: &Coordinate) -> i32 {
     (c1.x - c2.x).abs() as i32 + (c1.y - c2.y).abs() as i32
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back_its_weights() {
+        let weights = [0.5, -1.25, 3.0, 0.0, -0.125, 7.5];
+        assert_eq!(AreaEval::new(weights).weights(), weights);
+    }
+
+    #[test]
+    fn loads_weights_from_toml() {
+        let text = "weights = [0.5, -1.25, 3.0, 0.0, -0.125, 7.5]\n\n[metadata]\ntest_loss = 0.2\n";
+        let eval = AreaEval::from_toml(text).unwrap();
+        assert_eq!(eval.weights(), [0.5, -1.25, 3.0, 0.0, -0.125, 7.5]);
+    }
+}
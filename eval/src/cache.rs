@@ -0,0 +1,134 @@
+//! Fixed size evaluation cache.
+//!
+//! Wraps any [`Evaluator`] and remembers scores keyed by the position's Zobrist hash and the
+//! perspective it was scored from. Collisions in a slot simply overwrite the old entry.
+use std::cell::{Cell, RefCell};
+
+use board::useful_board::Game;
+
+use crate::Evaluator;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStatistics {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStatistics {
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct CacheEntry {
+    hash: u64,
+    perspective: Option<usize>,
+    score: f64,
+}
+
+pub struct EvalCache<E: Evaluator> {
+    pub evaluator: E,
+    entries: RefCell<Vec<Option<CacheEntry>>>,
+    mask: usize,
+    hits: Cell<u64>,
+    misses: Cell<u64>,
+}
+
+impl<E: Evaluator> EvalCache<E> {
+    /// Create a cache with room for `size` entries, rounded up to a power of two.
+    pub fn new(evaluator: E, size: usize) -> EvalCache<E> {
+        let size = size.max(1).next_power_of_two();
+        EvalCache {
+            evaluator,
+            entries: RefCell::new(vec![None; size]),
+            mask: size - 1,
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+        }
+    }
+
+    pub fn statistics(&self) -> CacheStatistics {
+        CacheStatistics {
+            hits: self.hits.get(),
+            misses: self.misses.get(),
+        }
+    }
+
+    /// Reset the hit and miss counters, keeping the cached scores.
+    pub fn reset_statistics(&self) {
+        self.hits.set(0);
+        self.misses.set(0);
+    }
+
+    /// Drop every cached score.
+    pub fn clear(&self) {
+        self.entries.borrow_mut().fill(None);
+    }
+}
+
+impl<E: Evaluator> Evaluator for EvalCache<E> {
+    fn score(&self, position: &Game) -> f64 {
+        let hash = position.zobrist_hash();
+        let perspective = position.perspective();
+        let slot = hash as usize & self.mask;
+        if let Some(entry) = self.entries.borrow()[slot] {
+            if entry.hash == hash && entry.perspective == perspective {
+                self.hits.set(self.hits.get() + 1);
+                return entry.score;
+            }
+        }
+        self.misses.set(self.misses.get() + 1);
+        let score = self.evaluator.score(position);
+        self.entries.borrow_mut()[slot] = Some(CacheEntry {
+            hash,
+            perspective,
+            score,
+        });
+        score
+    }
+
+    fn cache_statistics(&self) -> CacheStatistics {
+        self.statistics()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use board::test_support::{game, snake};
+
+    use super::*;
+
+    struct Counter(Cell<u32>);
+
+    impl Evaluator for Counter {
+        fn score(&self, position: &Game) -> f64 {
+            self.0.set(self.0.get() + 1);
+            position.perspective().unwrap() as f64
+        }
+    }
+
+    #[test]
+    fn caches_per_perspective() {
+        let mut game = Game {
+            turn: 4,
+            ..game(
+                "a",
+                vec![snake("a", &[(1, 1), (1, 0)]), snake("b", &[(5, 1), (5, 0)])],
+                &[(3, 3)],
+            )
+        };
+        let cache = EvalCache::new(Counter(Cell::new(0)), 1000);
+        assert_eq!(cache.score(&game), 0.0);
+        assert_eq!(cache.score(&game), 0.0);
+        game.you_id = "b".to_string();
+        assert_eq!(cache.score(&game), 1.0);
+        assert_eq!(cache.evaluator.0.get(), 2);
+        assert_eq!(cache.statistics(), CacheStatistics { hits: 1, misses: 2 });
+    }
+}
@@ -1,7 +1,37 @@
 pub mod area_eval;
+pub mod cache;
+pub mod nnue;
+pub mod search;
+pub mod terminal;
 
 use board::useful_board::Game;
 
+use crate::cache::CacheStatistics;
+
+/// Anything that can score a position from the perspective of `you_id`.
+pub trait Evaluator {
+    fn score(&self, position: &Game) -> f64;
+
+    /// Lookups in the evaluator's cache, all zero for evaluators without one.
+    fn cache_statistics(&self) -> CacheStatistics {
+        CacheStatistics::default()
+    }
+}
+
+impl Evaluator for area_eval::AreaEval {
+    fn score(&self, position: &Game) -> f64 {
+        area_eval::AreaEval::score(self, position)
+    }
+}
+
+impl Evaluator for nnue::Nnue {
+    /// Panics on boards larger than the net supports, check them with [`nnue::check_board`]
+    /// before searching.
+    fn score(&self, position: &Game) -> f64 {
+        nnue::Nnue::score(self, position).unwrap_or_else(|e| panic!("{e}"))
+    }
+}
+
 pub fn add(left: usize, right: usize) -> usize {
     left + right
 }
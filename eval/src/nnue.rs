@@ -0,0 +1,631 @@
+//! Small NNUE-style evaluator.
+//!
+//! The input layer is a sparse one-hot encoding of (square, piece type, owner relative to
+//! `you_id`), so a position only ever has a few dozen active inputs. The first layer is kept
+//! in an [`Accumulator`] which can be updated incrementally from a [`FeatureDelta`] when a move
+//! is made, instead of being recomputed from scratch.
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use board::{
+    useful_board::{Board, Game, Snake},
+    Coordinate,
+};
+
+/// Width of the largest supported board.
+pub const BOARD_WIDTH: usize = 11;
+/// Number of squares on the largest supported board.
+pub const SQUARES: usize = BOARD_WIDTH * BOARD_WIDTH;
+/// Number of sparse inputs: one plane per (piece, relation) pair plus one plane for food.
+pub const INPUTS: usize = SQUARES * (Piece::COUNT * Relation::COUNT + 1);
+
+/// Magic bytes at the start of a weight file.
+const MAGIC: &[u8; 4] = b"ONNU";
+/// Version of the weight file format.
+const VERSION: u32 = 1;
+/// Largest hidden layer a weight file may ask for, so a corrupt size can't exhaust memory.
+pub const MAX_HIDDEN: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Piece {
+    Head,
+    Body,
+    Tail,
+}
+
+impl Piece {
+    const COUNT: usize = 3;
+    const ALL: [Piece; Piece::COUNT] = [Piece::Head, Piece::Body, Piece::Tail];
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Relation {
+    Mine,
+    Theirs,
+}
+
+impl Relation {
+    const COUNT: usize = 2;
+}
+
+/// Index of the input for a snake piece on a square.
+pub fn piece_feature(square: usize, piece: Piece, relation: Relation) -> usize {
+    (relation as usize * Piece::COUNT + piece as usize) * SQUARES + square
+}
+
+/// Index of the input for food on a square.
+pub fn food_feature(square: usize) -> usize {
+    Piece::COUNT * Relation::COUNT * SQUARES + square
+}
+
+/// A board with squares the inputs have no room for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnsupportedBoard {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl fmt::Display for UnsupportedBoard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "a {}x{} board is larger than the {BOARD_WIDTH}x{BOARD_WIDTH} the nnue supports",
+            self.width, self.height
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedBoard {}
+
+/// Check that every square of a board has inputs of its own.
+pub fn check_board(board: &Board) -> Result<(), UnsupportedBoard> {
+    if board.width as usize > BOARD_WIDTH || board.height as usize > BOARD_WIDTH {
+        return Err(UnsupportedBoard {
+            width: board.width,
+            height: board.height,
+        });
+    }
+    Ok(())
+}
+
+// the square of a coordinate, `None` off the board
+fn square_index(coord: &Coordinate) -> Option<usize> {
+    if coord.x < 0 || coord.y < 0 {
+        return None;
+    }
+    let (x, y) = (coord.x as usize, coord.y as usize);
+    if x >= BOARD_WIDTH || y >= BOARD_WIDTH {
+        return None;
+    }
+    Some(y * BOARD_WIDTH + x)
+}
+
+fn relation(position: &Game, snake: &Snake) -> Relation {
+    if snake.id == position.you_id {
+        Relation::Mine
+    } else {
+        Relation::Theirs
+    }
+}
+
+fn piece_at(idx: usize, len: usize) -> Piece {
+    if idx == 0 {
+        Piece::Head
+    } else if idx + 1 == len {
+        Piece::Tail
+    } else {
+        Piece::Body
+    }
+}
+
+// how many pieces or food turn an input on
+fn count(position: &Game, feature: usize) -> i32 {
+    let square = Some(feature % SQUARES);
+    let plane = feature / SQUARES;
+    if plane == Piece::COUNT * Relation::COUNT {
+        let food = position.board.food.iter();
+        return food.filter(|x| square_index(x) == square).count() as i32;
+    }
+    let (relation, piece) = (plane / Piece::COUNT, Piece::ALL[plane % Piece::COUNT]);
+    let mut count = 0;
+    for snake in &position.board.snakes {
+        if self::relation(position, snake) as usize != relation {
+            continue;
+        }
+        let len = snake.body.len();
+        // heads and tails are only ever at the ends
+        let indices = match piece {
+            Piece::Head => 0..len.min(1),
+            Piece::Tail => len.saturating_sub(1).max(1)..len,
+            Piece::Body => 1..len.saturating_sub(1).max(1),
+        };
+        count += indices
+            .filter(|&idx| square_index(&snake.body[idx]) == square)
+            .count() as i32;
+    }
+    count
+}
+
+/// All the active inputs for a position, from the perspective of `position.you_id`.
+///
+/// The returned indices are sorted and deduplicated, so stacked segments at the start of a
+/// game only count once. Boards larger than [`BOARD_WIDTH`] are rejected rather than having
+/// their outer squares dropped.
+pub fn active_features(position: &Game) -> Result<Vec<usize>, UnsupportedBoard> {
+    check_board(&position.board)?;
+    let mut features = vec![];
+    for snake in &position.board.snakes {
+        let relation = relation(position, snake);
+        for (idx, segment) in snake.body.iter().enumerate() {
+            if let Some(square) = square_index(segment) {
+                let piece = piece_at(idx, snake.body.len());
+                features.push(piece_feature(square, piece, relation));
+            }
+        }
+    }
+    for food in &position.board.food {
+        if let Some(square) = square_index(food) {
+            features.push(food_feature(square));
+        }
+    }
+    features.sort_unstable();
+    features.dedup();
+    Ok(features)
+}
+
+/// The inputs that turn on and off between two positions.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FeatureDelta {
+    pub added: Vec<usize>,
+    pub removed: Vec<usize>,
+}
+
+impl FeatureDelta {
+    /// Compute the delta that takes the accumulator for `before` to the one for `after`.
+    ///
+    /// `after` has to follow `before` by one turn on the same board, from the same
+    /// perspective: every snake still on the board moved one square, its body following the
+    /// head, and maybe ate. A
+    /// moved body keeps its pieces except at the head and the tail, so only those segments,
+    /// the bodies of snakes that were eliminated or appeared, and the food are looked at.
+    pub fn between(before: &Game, after: &Game) -> FeatureDelta {
+        debug_assert_eq!(before.you_id, after.you_id);
+        debug_assert!(check_board(&before.board).is_ok());
+        // inputs whose number of pieces changes, by how much
+        let mut changes: Vec<(usize, i32)> = vec![];
+        // a snake piece, or food when `piece` is `None`
+        let mut change = |segment: &Coordinate, piece: Option<(Piece, Relation)>, by: i32| {
+            if let Some(square) = square_index(segment) {
+                let feature = match piece {
+                    Some((piece, relation)) => piece_feature(square, piece, relation),
+                    None => food_feature(square),
+                };
+                changes.push((feature, by));
+            }
+        };
+        for snake in &before.board.snakes {
+            let relation = relation(before, snake);
+            let piece = |idx, len| Some((piece_at(idx, len), relation));
+            let Some(moved) = after.board.snakes.iter().find(|x| x.id == snake.id) else {
+                for (idx, segment) in snake.body.iter().enumerate() {
+                    change(segment, piece(idx, snake.body.len()), -1);
+                }
+                continue;
+            };
+            let (old, new) = (&snake.body, &moved.body);
+            let (n, m) = (old.len(), new.len());
+            debug_assert!(old
+                .iter()
+                .zip(&new[1..])
+                .take(n.saturating_sub(1))
+                .all(|(a, b)| a == b));
+            // segment `idx` moves to `idx + 1`, and the old tail is dropped or, after eating,
+            // replaced by a copy of the new one. That leaves the same body pieces everywhere
+            // but near the ends.
+            let mut ends = vec![0, n.saturating_sub(2), n.saturating_sub(1)];
+            ends.dedup();
+            for idx in ends.into_iter().filter(|&x| x < n) {
+                change(&old[idx], piece(idx, n), -1);
+            }
+            let mut ends: Vec<_> = [0, 1].into_iter().chain(n.saturating_sub(1)..m).collect();
+            ends.sort_unstable();
+            ends.dedup();
+            for idx in ends.into_iter().filter(|&x| x < m) {
+                change(&new[idx], piece(idx, m), 1);
+            }
+        }
+        for snake in &after.board.snakes {
+            if before.board.snakes.iter().all(|x| x.id != snake.id) {
+                let relation = relation(after, snake);
+                for (idx, segment) in snake.body.iter().enumerate() {
+                    change(
+                        segment,
+                        Some((piece_at(idx, snake.body.len()), relation)),
+                        1,
+                    );
+                }
+            }
+        }
+        for food in &before.board.food {
+            change(food, None, -1);
+        }
+        for food in &after.board.food {
+            change(food, None, 1);
+        }
+        changes.sort_unstable();
+
+        let mut delta = FeatureDelta::default();
+        for group in changes.chunk_by(|a, b| a.0 == b.0) {
+            let feature = group[0].0;
+            let net: i32 = group.iter().map(|x| x.1).sum();
+            if net == 0 {
+                continue;
+            }
+            let count = count(before, feature);
+            if count == 0 {
+                delta.added.push(feature);
+            } else if count + net == 0 {
+                delta.removed.push(feature);
+            }
+        }
+        delta
+    }
+
+    /// The delta that undoes this one.
+    pub fn inverse(&self) -> FeatureDelta {
+        FeatureDelta {
+            added: self.removed.clone(),
+            removed: self.added.clone(),
+        }
+    }
+}
+
+/// Network weights.
+///
+/// The first layer maps [`INPUTS`] sparse inputs to `hidden` neurons, followed by a clipped
+/// ReLU, a single output neuron and a sigmoid.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Nnue {
+    pub hidden: usize,
+    /// Input weights, `hidden` values per input.
+    pub input_weights: Vec<f32>,
+    pub input_bias: Vec<f32>,
+    pub output_weights: Vec<f32>,
+    pub output_bias: f32,
+}
+
+impl Nnue {
+    /// A network with every weight set to zero.
+    pub fn zeroed(hidden: usize) -> Nnue {
+        Nnue {
+            hidden,
+            input_weights: vec![0.0; INPUTS * hidden],
+            input_bias: vec![0.0; hidden],
+            output_weights: vec![0.0; hidden],
+            output_bias: 0.0,
+        }
+    }
+
+    /// The column of first layer weights belonging to an input.
+    pub fn input_column(&self, feature: usize) -> &[f32] {
+        &self.input_weights[feature * self.hidden..(feature + 1) * self.hidden]
+    }
+
+    /// Build a fresh accumulator for a position.
+    pub fn accumulator(&self, position: &Game) -> Result<Accumulator, UnsupportedBoard> {
+        let mut acc = Accumulator {
+            values: self.input_bias.clone(),
+        };
+        for feature in active_features(position)? {
+            acc.add_feature(self, feature);
+        }
+        Ok(acc)
+    }
+
+    /// Finish the evaluation from an up to date accumulator.
+    pub fn output(&self, acc: &Accumulator) -> f64 {
+        let mut sum = self.output_bias;
+        for (value, weight) in acc.values.iter().zip(&self.output_weights) {
+            sum += clipped_relu(*value) * weight;
+        }
+        sigmoid(sum as f64)
+    }
+
+    /// Evaluate a position from scratch.
+    pub fn score(&self, position: &Game) -> Result<f64, UnsupportedBoard> {
+        Ok(self.output(&self.accumulator(position)?))
+    }
+
+    /// Load a weight file written by [`Nnue::save`].
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Nnue> {
+        Nnue::read_from(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Write the network to a weight file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Read the little endian weight format:
+    /// magic, version, input count, hidden size, input weights, input bias,
+    /// output weights, output bias.
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Nnue> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not an nnue weight file"));
+        }
+        let version = read_u32(reader)?;
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "unsupported nnue weight file version {version}"
+            )));
+        }
+        let inputs = read_u32(reader)? as usize;
+        if inputs != INPUTS {
+            return Err(invalid_data(format!(
+                "weight file has {inputs} inputs, expected {INPUTS}"
+            )));
+        }
+        let hidden = read_u32(reader)? as usize;
+        if hidden == 0 || hidden > MAX_HIDDEN {
+            return Err(invalid_data(format!(
+                "weight file has {hidden} hidden neurons, expected 1 to {MAX_HIDDEN}"
+            )));
+        }
+        let weights = INPUTS
+            .checked_mul(hidden)
+            .ok_or_else(|| invalid_data("weight file is too large"))?;
+        Ok(Nnue {
+            hidden,
+            input_weights: read_f32s(reader, weights)?,
+            input_bias: read_f32s(reader, hidden)?,
+            output_weights: read_f32s(reader, hidden)?,
+            output_bias: read_f32s(reader, 1)?[0],
+        })
+    }
+
+    /// Write the format read by [`Nnue::read_from`].
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(INPUTS as u32).to_le_bytes())?;
+        writer.write_all(&(self.hidden as u32).to_le_bytes())?;
+        for value in self
+            .input_weights
+            .iter()
+            .chain(&self.input_bias)
+            .chain(&self.output_weights)
+            .chain([&self.output_bias])
+        {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// First layer output for one position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Accumulator {
+    pub values: Vec<f32>,
+}
+
+impl Accumulator {
+    pub fn add_feature(&mut self, net: &Nnue, feature: usize) {
+        for (value, weight) in self.values.iter_mut().zip(net.input_column(feature)) {
+            *value += weight;
+        }
+    }
+
+    pub fn remove_feature(&mut self, net: &Nnue, feature: usize) {
+        for (value, weight) in self.values.iter_mut().zip(net.input_column(feature)) {
+            *value -= weight;
+        }
+    }
+
+    pub fn apply(&mut self, net: &Nnue, delta: &FeatureDelta) {
+        for &feature in &delta.removed {
+            self.remove_feature(net, feature);
+        }
+        for &feature in &delta.added {
+            self.add_feature(net, feature);
+        }
+    }
+}
+
+/// Stack of accumulators that follows make / unmake in a search.
+///
+/// Making a move pushes an updated copy of the top accumulator, unmaking it just pops, so
+/// no subtraction error builds up along a line.
+pub struct AccumulatorStack {
+    stack: Vec<Accumulator>,
+}
+
+impl AccumulatorStack {
+    pub fn new(net: &Nnue, root: &Game) -> Result<AccumulatorStack, UnsupportedBoard> {
+        Ok(AccumulatorStack {
+            stack: vec![net.accumulator(root)?],
+        })
+    }
+
+    /// The accumulator for the current position.
+    pub fn current(&self) -> &Accumulator {
+        self.stack.last().unwrap()
+    }
+
+    pub fn make(&mut self, net: &Nnue, delta: &FeatureDelta) {
+        let mut next = self.current().clone();
+        next.apply(net, delta);
+        self.stack.push(next);
+    }
+
+    pub fn unmake(&mut self) {
+        assert!(self.stack.len() > 1, "unmake called on the root position");
+        self.stack.pop();
+    }
+}
+
+/// Clipped ReLU used between the two layers.
+pub fn clipped_relu(value: f32) -> f32 {
+    value.clamp(0.0, 1.0)
+}
+
+fn sigmoid(value: f64) -> f64 {
+    1.0 / (1.0 + (-value).exp())
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_f32s<R: Read>(reader: &mut R, count: usize) -> io::Result<Vec<f32>> {
+    let len = count
+        .checked_mul(4)
+        .ok_or_else(|| invalid_data("weight file is too large"))?;
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf)?;
+    Ok(buf
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use board::test_support::{game, snake};
+
+    use super::*;
+
+    fn duel(me: &[(i8, i8)], them: &[(i8, i8)], food: &[(i8, i8)]) -> Game {
+        game("me", vec![snake("me", me), snake("them", them)], food)
+    }
+
+    fn test_net() -> Nnue {
+        let mut net = Nnue::zeroed(8);
+        for (idx, weight) in net.input_weights.iter_mut().enumerate() {
+            *weight = ((idx * 37 % 101) as f32 - 50.0) / 200.0;
+        }
+        for (idx, weight) in net.output_weights.iter_mut().enumerate() {
+            *weight = idx as f32 / 4.0 - 1.0;
+        }
+        net.output_bias = 0.1;
+        net
+    }
+
+    #[test]
+    fn incremental_update_matches_refresh() {
+        let net = test_net();
+        let before = duel(
+            &[(1, 1), (1, 2), (1, 3)],
+            &[(9, 9), (9, 8), (9, 7)],
+            &[(5, 5), (2, 1)],
+        );
+        let after = duel(
+            &[(2, 1), (1, 1), (1, 2), (1, 2)],
+            &[(9, 10), (9, 9), (9, 8)],
+            &[(5, 5)],
+        );
+        let mut stack = AccumulatorStack::new(&net, &before).unwrap();
+        stack.make(&net, &FeatureDelta::between(&before, &after));
+        let fresh = net.accumulator(&after).unwrap();
+        for (a, b) in stack.current().values.iter().zip(&fresh.values) {
+            assert!((a - b).abs() < 1e-5);
+        }
+        stack.unmake();
+        assert_eq!(stack.current(), &net.accumulator(&before).unwrap());
+    }
+
+    #[test]
+    fn delta_matches_full_diff() {
+        use board::rules::Move::{self, *};
+
+        // stacked at the start, eating while stacked and not, and one snake running off the board
+        let mut position = duel(
+            &[(5, 5); 3],
+            &[(1, 8); 3],
+            &[(5, 6), (5, 7), (2, 9), (3, 5)],
+        );
+        let moves: [[Move; 2]; 9] = [
+            [Up, Up],
+            [Up, Right],
+            [Left, Up],
+            [Left, Right],
+            [Down, Right],
+            [Down, Down],
+            [Right, Right],
+            [Up, Up],
+            [Right, Up],
+        ];
+        for moves in moves {
+            let mut next = position.clone();
+            next.advance(&moves);
+            let (old, new) = (
+                active_features(&position).unwrap(),
+                active_features(&next).unwrap(),
+            );
+            let mut delta = FeatureDelta::between(&position, &next);
+            delta.added.sort_unstable();
+            delta.removed.sort_unstable();
+            assert_eq!(
+                delta,
+                FeatureDelta {
+                    added: new.iter().filter(|x| !old.contains(x)).copied().collect(),
+                    removed: old.iter().filter(|x| !new.contains(x)).copied().collect(),
+                },
+                "{moves:?}"
+            );
+            position = next;
+        }
+        assert_eq!(position.board.snakes.len(), 1);
+    }
+
+    #[test]
+    fn rejects_boards_larger_than_the_net() {
+        let net = test_net();
+        let mut position = duel(&[(1, 1), (1, 2)], &[(15, 15), (15, 16)], &[(3, 3)]);
+        position.board.width = 19;
+        position.board.height = 19;
+        let error = UnsupportedBoard {
+            width: 19,
+            height: 19,
+        };
+        assert_eq!(active_features(&position), Err(error));
+        assert_eq!(net.score(&position), Err(error));
+        // smaller boards fit in the corner of the inputs
+        position.board.width = 7;
+        position.board.height = 7;
+        position.board.snakes.pop();
+        assert!(net.score(&position).is_ok());
+    }
+
+    #[test]
+    fn weight_file_round_trips() {
+        let net = test_net();
+        let mut buf = vec![];
+        net.write_to(&mut buf).unwrap();
+        assert_eq!(Nnue::read_from(&mut &buf[..]).unwrap(), net);
+    }
+
+    #[test]
+    fn rejects_corrupt_hidden_sizes() {
+        let mut buf = vec![];
+        test_net().write_to(&mut buf).unwrap();
+        for hidden in [0, MAX_HIDDEN as u32 + 1, u32::MAX] {
+            buf[12..16].copy_from_slice(&hidden.to_le_bytes());
+            let err = Nnue::read_from(&mut &buf[..]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{hidden}");
+        }
+    }
+}
@@ -0,0 +1,266 @@
+//! Fixed depth search over simultaneous moves.
+//!
+//! Every turn all snakes move at once, so the search is paranoid: for each of our moves it
+//! assumes the other snakes answer with whichever combination of moves is worst for us. Depth
+//! is counted in turns, and the leaves are scored by an [`Evaluator`] from our perspective.
+//! Finished games are scored by [`Terminal::score`] with the number of turns from the root.
+use std::{fmt, iter::Sum, ops::Add};
+
+use board::{rules::Move, useful_board::Game};
+
+use crate::{cache::CacheStatistics, terminal::Terminal, Evaluator};
+
+pub struct Search<E: Evaluator> {
+    pub evaluator: E,
+    /// positions visited since the search was created
+    pub nodes: u64,
+}
+
+/// Work done by one or more searches.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchStatistics {
+    /// positions visited
+    pub nodes: u64,
+    /// lookups in the evaluation caches
+    pub cache: CacheStatistics,
+}
+
+impl Add for SearchStatistics {
+    type Output = SearchStatistics;
+
+    fn add(self, other: SearchStatistics) -> SearchStatistics {
+        SearchStatistics {
+            nodes: self.nodes + other.nodes,
+            cache: CacheStatistics {
+                hits: self.cache.hits + other.cache.hits,
+                misses: self.cache.misses + other.cache.misses,
+            },
+        }
+    }
+}
+
+impl Sum for SearchStatistics {
+    fn sum<I: Iterator<Item = SearchStatistics>>(iter: I) -> SearchStatistics {
+        iter.fold(SearchStatistics::default(), Add::add)
+    }
+}
+
+impl fmt::Display for SearchStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} nodes, cache {} hits / {} misses ({:.1}%)",
+            self.nodes,
+            self.cache.hits,
+            self.cache.misses,
+            self.cache.hit_rate() * 100.0
+        )
+    }
+}
+
+/// The outcome of a search: our move, its score and the position the score came from.
+#[derive(Clone, Debug)]
+pub struct Line {
+    pub mov: Move,
+    pub score: f64,
+    /// the end of the principal variation, from the same perspective as the root
+    pub leaf: Game,
+}
+
+impl<E: Evaluator> Search<E> {
+    pub fn new(evaluator: E) -> Search<E> {
+        Search {
+            evaluator,
+            nodes: 0,
+        }
+    }
+
+    /// Nodes visited so far, next to the lookups in the evaluator's cache if it has one.
+    pub fn statistics(&self) -> SearchStatistics {
+        SearchStatistics {
+            nodes: self.nodes,
+            cache: self.evaluator.cache_statistics(),
+        }
+    }
+
+    /// The best move for `you_id` looking `depth` turns ahead, and its score.
+    ///
+    /// Ties go to the move that comes first in [`Move::ALL`], so the result is deterministic.
+    pub fn best_move(&mut self, position: &Game, depth: u32) -> (Move, f64) {
+        let line = self.best_line(position, depth);
+        (line.mov, line.score)
+    }
+
+    /// Like [`Search::best_move`], but also returns the leaf of the principal variation.
+    ///
+    /// A root where the game is already over, including one we are no longer on, has nothing
+    /// to search: it gets its terminal score and the first move of [`Move::ALL`].
+    pub fn best_line(&mut self, position: &Game, depth: u32) -> Line {
+        if let Some(terminal) = Terminal::detect(position) {
+            self.nodes += 1;
+            return Line {
+                mov: Move::ALL[0],
+                score: terminal.score(0),
+                leaf: position.clone(),
+            };
+        }
+        let mut best: Option<Line> = None;
+        for mine in Move::ALL {
+            let floor = best.as_ref().map_or(f64::NEG_INFINITY, |x| x.score);
+            let (score, leaf) = self.reply(position, mine, depth.max(1), 1, floor);
+            if score > floor {
+                best = Some(Line {
+                    mov: mine,
+                    score,
+                    leaf,
+                });
+            }
+        }
+        best.expect("there is always a move")
+    }
+
+    // the value of a position `ply` turns after the root, with `depth` turns left to search
+    fn value(&mut self, position: &Game, depth: u32, ply: u32) -> (f64, Game) {
+        self.nodes += 1;
+        if let Some(terminal) = Terminal::detect(position) {
+            return (terminal.score(ply), position.clone());
+        }
+        if depth == 0 {
+            return (self.evaluator.score(position), position.clone());
+        }
+        let mut best: Option<(f64, Game)> = None;
+        for mine in Move::ALL {
+            let floor = best.as_ref().map_or(f64::NEG_INFINITY, |x| x.0);
+            let line = self.reply(position, mine, depth, ply + 1, floor);
+            if line.0 > floor {
+                best = Some(line);
+            }
+        }
+        best.expect("there is always a move")
+    }
+
+    // the worst score the other snakes can hold us to after `mine`, which leads to a position
+    // `ply` turns after the root, stopping early once it's no better than `floor`, the best we
+    // already have elsewhere
+    fn reply(
+        &mut self,
+        position: &Game,
+        mine: Move,
+        depth: u32,
+        ply: u32,
+        floor: f64,
+    ) -> (f64, Game) {
+        let me = position.perspective().expect("we are not on the board");
+        let others = position.board.snakes.len() - 1;
+        let mut worst: Option<(f64, Game)> = None;
+        for combination in 0..Move::ALL.len().pow(others as u32) {
+            let mut rest = combination;
+            let moves: Vec<Move> = (0..=others)
+                .map(|slot| {
+                    if slot == me {
+                        mine
+                    } else {
+                        let theirs = Move::ALL[rest % Move::ALL.len()];
+                        rest /= Move::ALL.len();
+                        theirs
+                    }
+                })
+                .collect();
+            let mut next = position.clone();
+            next.advance(&moves);
+            let line = self.value(&next, depth - 1, ply);
+            if worst.as_ref().is_none_or(|x| line.0 < x.0) {
+                worst = Some(line);
+            }
+            if worst.as_ref().is_some_and(|x| x.0 <= floor) {
+                break;
+            }
+        }
+        worst.expect("there is always a reply")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use board::test_support::{game, snake};
+
+    use crate::{area_eval::AreaEval, cache::EvalCache};
+
+    use super::*;
+
+    #[test]
+    fn avoids_losing_moves() {
+        // in the corner, only up is safe
+        let position = Game {
+            turn: 5,
+            ..game(
+                "me",
+                vec![
+                    snake("me", &[(0, 0), (1, 0), (2, 0)]),
+                    snake("them", &[(8, 8), (8, 9), (8, 10)]),
+                ],
+                &[],
+            )
+        };
+        let mut search = Search::new(AreaEval::new([0.0, 0.0, 0.0, 0.0, 1.0, 0.0]));
+        assert_eq!(search.best_move(&position, 1).0, Move::Up);
+        let (mov, score) = search.best_move(&position, 2);
+        assert_eq!(mov, Move::Up);
+        assert!(score > 0.0 && score < 1.0);
+
+        // they are boxed in by their own body, so any safe move wins next turn
+        let position = Game {
+            turn: 5,
+            ..game(
+                "me",
+                vec![
+                    snake("me", &[(5, 5), (4, 5), (3, 5)]),
+                    snake("them", &[(10, 10), (10, 9), (9, 9), (9, 10), (8, 10)]),
+                ],
+                &[],
+            )
+        };
+        let line = search.best_line(&position, 2);
+        assert_eq!(line.score, Terminal::Won.score(1));
+        assert_eq!(Terminal::detect(&line.leaf), Some(Terminal::Won));
+        // the same win from a later turn of the game scores the same
+        let later = Game {
+            turn: 250,
+            ..position.clone()
+        };
+        assert_eq!(search.best_line(&later, 2).score, line.score);
+
+        // a root we are already dead in is lost, not a panic
+        let mut dead = position.clone();
+        dead.you_id = "gone".to_string();
+        let line = search.best_line(&dead, 2);
+        assert_eq!(line.score, Terminal::Lost.score(0));
+    }
+
+    #[test]
+    fn counts_cache_lookups_next_to_nodes() {
+        // stacked, as at the start, so no move ends the game
+        let position = game(
+            "me",
+            vec![
+                snake("me", &[(5, 5), (5, 5), (5, 5)]),
+                snake("them", &[(8, 8), (8, 8), (8, 8)]),
+            ],
+            &[],
+        );
+        let eval = AreaEval::new([0.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        let mut search = Search::new(EvalCache::new(eval.clone(), 1 << 10));
+        search.best_move(&position, 1);
+        let first = search.statistics();
+        // at depth 1 every node is a leaf, and the same search again only hits the cache
+        assert_eq!(first.cache.hits + first.cache.misses, first.nodes);
+        search.best_move(&position, 1);
+        let second = search.statistics();
+        assert_eq!(second.cache.misses, first.cache.misses);
+        assert_eq!(second.nodes, 2 * first.nodes);
+
+        let mut uncached = Search::new(eval);
+        uncached.best_move(&position, 1);
+        assert_eq!(uncached.statistics().cache, CacheStatistics::default());
+    }
+}
@@ -0,0 +1,96 @@
+//! Terminal position detection and scoring.
+//!
+//! Evaluations are win probabilities in `0.0..=1.0`, so terminal scores are placed far outside
+//! that range. Wins and losses are adjusted by how many turns past the root of the search they
+//! happen, so among won positions the earliest win scores highest and among lost positions the
+//! latest loss scores highest, whatever turn of the game the search started on.
+use board::useful_board::Game;
+
+/// Score of a win at the root. Wins further from the root score one point less per turn.
+pub const WIN_SCORE: f64 = 1_000_000.0;
+/// Score of a loss at the root. Losses further from the root score one point more per turn.
+pub const LOSS_SCORE: f64 = -WIN_SCORE;
+/// Score of a draw by simultaneous elimination.
+pub const DRAW_SCORE: f64 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Terminal {
+    /// we are the only snake left alive
+    Won,
+    /// we have been eliminated and someone else is still alive
+    Lost,
+    /// every snake has been eliminated
+    Draw,
+}
+
+impl Terminal {
+    /// Detect whether the game is over from the perspective of `you_id`.
+    pub fn detect(position: &Game) -> Option<Terminal> {
+        let me_alive = position
+            .board
+            .snakes
+            .iter()
+            .any(|x| x.id == position.you_id && x.is_alive());
+        let others_alive = position
+            .board
+            .snakes
+            .iter()
+            .any(|x| x.id != position.you_id && x.is_alive());
+        match (me_alive, others_alive) {
+            (true, true) => None,
+            (true, false) => Some(Terminal::Won),
+            (false, true) => Some(Terminal::Lost),
+            (false, false) => Some(Terminal::Draw),
+        }
+    }
+
+    /// The fixed score of this result, reached `plies` turns after the root of the search.
+    pub fn score(&self, plies: u32) -> f64 {
+        match self {
+            Terminal::Won => WIN_SCORE - plies as f64,
+            Terminal::Lost => LOSS_SCORE + plies as f64,
+            Terminal::Draw => DRAW_SCORE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use board::{
+        test_support::{game, snake},
+        useful_board::Snake,
+    };
+
+    use super::*;
+
+    // snakes with the given health, all on one square
+    fn position(snakes: &[(&str, u8)]) -> Game {
+        let snakes = snakes.iter().map(|&(id, health)| Snake {
+            health,
+            ..snake(id, &[(1, 1)])
+        });
+        game("me", snakes.collect(), &[])
+    }
+
+    #[test]
+    fn detects_results() {
+        assert_eq!(Terminal::detect(&position(&[("me", 5), ("them", 5)])), None);
+        assert_eq!(
+            Terminal::detect(&position(&[("me", 5), ("them", 0)])),
+            Some(Terminal::Won)
+        );
+        assert_eq!(
+            Terminal::detect(&position(&[("them", 5)])),
+            Some(Terminal::Lost)
+        );
+        assert_eq!(Terminal::detect(&position(&[])), Some(Terminal::Draw));
+    }
+
+    #[test]
+    fn prefers_fast_wins_and_slow_losses() {
+        assert!(Terminal::Won.score(10) > Terminal::Won.score(20));
+        assert!(Terminal::Won.score(10_000) > 1.0);
+        assert!(Terminal::Lost.score(20) > Terminal::Lost.score(10));
+        assert!(Terminal::Lost.score(10_000) < 0.0);
+    }
+}
@@ -0,0 +1,319 @@
+//! Binary dataset format for [`ComputedEntry`] records.
+//!
+//! Layout, all little endian:
+//! *   magic `ORCD`, format version `u32`, header length in bytes `u32`
+//! *   feature count `u32`, entry count `u64`
+//! *   each feature name as a `u32` length followed by its utf-8 bytes
+//! *   free-form metadata as a `u32` length followed by its utf-8 bytes, see
+//!     [`crate::cache::CacheMetadata`]
+//! *   zero padding up to the header length, which is a multiple of 8
+//! *   the records, each one `f64` per feature, the result as an `f64`, turns until the end as
+//!     a `u32`, the game index as a `u32`, the turn as a `u32` and 4 reserved bytes
+//!
+//! Records are fixed size and 8 byte aligned, so a file can be memory mapped and indexed
+//! without parsing it, see [`MappedDataset`]. Training still decodes every record into memory
+//! first, since the split, standardisation and shuffling all rewrite the entries. What it gains
+//! over the JSON cache is a smaller file that loads without a parser, not a dataset larger than
+//! memory.
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use memmap2::Mmap;
+use nalgebra::SVector;
+use serde::Deserialize;
+use snake_tuner::database::Database;
+
+use crate::dataset::{ComputedEntry, DB, FEATURES};
+
+const MAGIC: &[u8; 4] = b"ORCD";
+/// Version of the binary format, bumped whenever the layout changes.
+pub const VERSION: u32 = 4;
+/// Size of one record in bytes.
+pub const RECORD_SIZE: usize = (FEATURES + 3) * 8;
+
+/// Write a dataset in the binary format.
+pub fn write<P: AsRef<Path>>(
+    path: P,
+    features: &[&str],
+    metadata: &str,
+    entries: &[ComputedEntry],
+) -> io::Result<()> {
+    assert_eq!(features.len(), FEATURES);
+    let mut header = vec![];
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    // header length, filled in below
+    header.extend_from_slice(&0u32.to_le_bytes());
+    header.extend_from_slice(&(features.len() as u32).to_le_bytes());
+    header.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+    for text in features.iter().chain([&metadata]) {
+        header.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        header.extend_from_slice(text.as_bytes());
+    }
+    header.resize(header.len().next_multiple_of(8), 0);
+    let header_len = header.len() as u32;
+    header[8..12].copy_from_slice(&header_len.to_le_bytes());
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&header)?;
+    for entry in entries {
+        writer.write_all(&record(entry))?;
+    }
+    writer.flush()
+}
+
+/// The bytes `entry` is stored as.
+pub fn record(entry: &ComputedEntry) -> [u8; RECORD_SIZE] {
+    let mut record = [0; RECORD_SIZE];
+    for (i, value) in entry.input.iter().chain([&entry.result]).enumerate() {
+        record[i * 8..i * 8 + 8].copy_from_slice(&value.to_le_bytes());
+    }
+    let rest = (FEATURES + 1) * 8;
+    for (i, value) in [entry.turns_until_end, entry.game, entry.turn]
+        .iter()
+        .enumerate()
+    {
+        record[rest + i * 4..rest + i * 4 + 4].copy_from_slice(&value.to_le_bytes());
+    }
+    record
+}
+
+/// A memory mapped binary dataset, which decodes records as they are read.
+pub struct MappedDataset {
+    mmap: Mmap,
+    features: Vec<String>,
+    metadata: String,
+    entries: usize,
+    header_len: usize,
+}
+
+impl MappedDataset {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<MappedDataset> {
+        let file = File::open(path)?;
+        // Safety: the file is only read, and datasets aren't modified while the tuner runs.
+        let mmap = unsafe { Mmap::map(&file)? };
+        if mmap.len() < 24 || &mmap[0..4] != MAGIC {
+            return Err(invalid_data("not a binary dataset".to_string()));
+        }
+        let version = read_u32(&mmap, 4);
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "dataset has format version {version}, expected {VERSION}"
+            )));
+        }
+        let header_len = read_u32(&mmap, 8) as usize;
+        let feature_count = read_u32(&mmap, 12) as usize;
+        let entries = u64::from_le_bytes(mmap[16..24].try_into().unwrap()) as usize;
+        if feature_count != FEATURES {
+            return Err(invalid_data(format!(
+                "dataset has {feature_count} features, expected {FEATURES}"
+            )));
+        }
+        // the feature names followed by the metadata
+        let mut texts = vec![];
+        let mut offset = 24;
+        for _ in 0..=feature_count {
+            let text = mmap
+                .get(offset..offset + 4)
+                .map(|x| read_u32(x, 0) as usize)
+                .and_then(|len| mmap.get(offset + 4..offset + 4 + len))
+                .ok_or_else(|| invalid_data("truncated header".to_string()))?;
+            texts.push(String::from_utf8_lossy(text).into_owned());
+            offset += 4 + text.len();
+        }
+        let metadata = texts.pop().unwrap();
+        let features = texts;
+        if offset.next_multiple_of(8) != header_len {
+            return Err(invalid_data(format!(
+                "header should be {} bytes long, found {header_len}",
+                offset.next_multiple_of(8)
+            )));
+        }
+        let len = entries
+            .checked_mul(RECORD_SIZE)
+            .and_then(|x| x.checked_add(header_len))
+            .ok_or_else(|| invalid_data(format!("dataset can't hold {entries} entries")))?;
+        if mmap.len() != len {
+            return Err(invalid_data(format!(
+                "dataset should be {len} bytes long, found {}",
+                mmap.len()
+            )));
+        }
+        Ok(MappedDataset {
+            mmap,
+            features,
+            metadata,
+            entries,
+            header_len,
+        })
+    }
+
+    /// Names of the features, in the order they are stored.
+    pub fn features(&self) -> &[String] {
+        &self.features
+    }
+
+    /// Metadata recorded when the dataset was written.
+    pub fn metadata(&self) -> &str {
+        &self.metadata
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+
+    /// Decode the record at `idx`.
+    pub fn entry(&self, idx: usize) -> ComputedEntry {
+        assert!(idx < self.entries);
+        let start = self.header_len + idx * RECORD_SIZE;
+        let record = &self.mmap[start..start + RECORD_SIZE];
+        let value = |i: usize| f64::from_le_bytes(record[i * 8..i * 8 + 8].try_into().unwrap());
+        ComputedEntry {
+            input: SVector::from_fn(|i, _| value(i)),
+            result: value(FEATURES),
+            turns_until_end: read_u32(record, (FEATURES + 1) * 8),
+            game: read_u32(record, (FEATURES + 1) * 8 + 4),
+            turn: read_u32(record, (FEATURES + 2) * 8),
+            output: 0.0,
+        }
+    }
+
+    /// Decode every record into memory, which is how the tuner trains on a dataset.
+    pub fn to_db(&self) -> DB {
+        DB {
+            entries: (0..self.entries).map(|x| self.entry(x)).collect(),
+        }
+    }
+}
+
+impl Database<FEATURES, ComputedEntry> for MappedDataset {
+    fn size(&self) -> usize {
+        self.entries
+    }
+
+    fn get(&self, idx: usize) -> ComputedEntry {
+        self.entry(idx)
+    }
+}
+
+// an entry of a JSON cache, including caches written before results and turn counts were
+// stored separately
+#[derive(Deserialize)]
+struct JsonEntry {
+    input: SVector<f64, FEATURES>,
+    #[serde(alias = "output")]
+    result: f64,
+    turns_until_end: Option<u32>,
+    turn: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct JsonDB {
+    entries: Vec<JsonEntry>,
+}
+
+/// Read the entries of a legacy `database.json` cache.
+///
+/// JSON caches don't record games, and most don't record turns, which are then stored as
+/// [`ComputedEntry::UNKNOWN_TURN`]. They were written one game at a time in turn order, so a new
+/// game is started whenever the turns until the end go up. Caches from before turns until the
+/// end were stored give every entry its own game.
+pub fn read_json<P: AsRef<Path>>(path: P) -> io::Result<Vec<ComputedEntry>> {
+    let db: JsonDB = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let mut game = 0;
+    let mut previous = None;
+    Ok(db
+        .entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, x)| {
+            let new_game = match (previous, x.turns_until_end) {
+                (Some(previous), Some(turns)) => turns > previous,
+                _ => i > 0,
+            };
+            game += new_game as u32;
+            previous = x.turns_until_end;
+            ComputedEntry {
+                input: x.input,
+                result: x.result,
+                turns_until_end: x.turns_until_end.unwrap_or(0),
+                game,
+                turn: x.turn.unwrap_or(ComputedEntry::UNKNOWN_TURN),
+                output: 0.0,
+            }
+        })
+        .collect())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_a_file() {
+        let entries: Vec<_> = (0..10)
+            .map(|x| ComputedEntry {
+                input: SVector::from_fn(|i, _| x as f64 * 0.5 - i as f64),
+                result: (x % 3) as f64 / 2.0,
+                turns_until_end: x * 7,
+                game: x / 3,
+                turn: x % 3,
+                output: 0.0,
+            })
+            .collect();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("database.bin");
+        let features = ["a", "bb", "ccc", "d", "e", "bias"];
+        write(&path, &features, "{\"source\":1}", &entries).unwrap();
+        let dataset = MappedDataset::open(&path).unwrap();
+        assert_eq!(dataset.features(), features);
+        assert_eq!(dataset.metadata(), "{\"source\":1}");
+        assert_eq!(dataset.to_db().entries, entries);
+
+        // a header length that doesn't match the header, and an entry count that overflows
+        let bytes = fs::read(&path).unwrap();
+        for (offset, value) in [(8, &[0xff; 4][..]), (16, &[0xff; 8][..])] {
+            let mut corrupt = bytes.clone();
+            corrupt[offset..offset + value.len()].copy_from_slice(value);
+            fs::write(&path, corrupt).unwrap();
+            assert!(MappedDataset::open(&path).is_err());
+        }
+    }
+
+    #[test]
+    fn reads_turns_from_json_caches_that_have_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("database.json");
+        let input = "[0.0, 0.0, 0.0, 0.0, 0.0, 1.0]";
+        fs::write(
+            &path,
+            format!(
+                r#"{{"entries": [
+                    {{"input": {input}, "result": 1.0, "turns_until_end": 3, "turn": 7}},
+                    {{"input": {input}, "output": 0.0, "turns_until_end": 9}}
+                ]}}"#
+            ),
+        )
+        .unwrap();
+        let entries = read_json(&path).unwrap();
+        assert_eq!(
+            entries.iter().map(|x| (x.game, x.turn)).collect::<Vec<_>>(),
+            vec![(0, 7), (1, ComputedEntry::UNKNOWN_TURN)]
+        );
+    }
+}
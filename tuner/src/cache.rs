@@ -0,0 +1,344 @@
+//! Checking that a precomputed dataset still matches what it was built from.
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::Path,
+    time::UNIX_EPOCH,
+};
+
+use combat_adapter::{Filter, Format, Perspective};
+use eval::area_eval::AreaEval;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    binary::{self, MappedDataset},
+    dataset::ComputedEntry,
+};
+
+/// Everything a cached dataset depends on, as the config asks for it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CacheKey {
+    /// the dump the positions were read from
+    pub source: String,
+    pub format: Format,
+    /// [`AreaEval::FEATURE_VERSION`] at the time the features were computed
+    pub feature_version: u32,
+    pub filter: Filter,
+    /// caches from before the option only ever held the recorded perspective
+    #[serde(default)]
+    pub perspective: Perspective,
+    pub augment_symmetries: bool,
+}
+
+impl CacheKey {
+    /// The key for a dataset built from `source` with the current features.
+    pub fn new(
+        source: &str,
+        format: Format,
+        filter: &Filter,
+        perspective: Perspective,
+        augment_symmetries: bool,
+    ) -> CacheKey {
+        CacheKey {
+            source: source.to_string(),
+            format,
+            feature_version: AreaEval::FEATURE_VERSION,
+            filter: filter.clone(),
+            perspective,
+            augment_symmetries,
+        }
+    }
+
+    /// Describe every way `self`, the key a cache was built with, differs from `wanted`.
+    pub fn differences(&self, wanted: &CacheKey) -> Vec<String> {
+        let mut differences = vec![];
+        if self.source != wanted.source {
+            differences.push(format!("built from {}, not {}", self.source, wanted.source));
+        }
+        if self.format != wanted.format {
+            differences.push(format!(
+                "read as {:?}, not {:?}",
+                self.format, wanted.format
+            ));
+        }
+        if self.feature_version != wanted.feature_version {
+            differences.push(format!(
+                "built with feature version {}, current is {}",
+                self.feature_version, wanted.feature_version
+            ));
+        }
+        if self.filter != wanted.filter {
+            differences.push(format!(
+                "built with filter {:?}, not {:?}",
+                self.filter, wanted.filter
+            ));
+        }
+        if self.perspective != wanted.perspective {
+            differences.push(format!(
+                "built with perspective {:?}, not {:?}",
+                self.perspective, wanted.perspective
+            ));
+        }
+        if self.augment_symmetries != wanted.augment_symmetries {
+            differences.push(format!(
+                "built with augment_symmetries = {}, not {}",
+                self.augment_symmetries, wanted.augment_symmetries
+            ));
+        }
+        differences
+    }
+}
+
+/// Size and modification time of a dump, which are checked before hashing it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceStamp {
+    /// total length in bytes
+    pub len: u64,
+    /// latest modification, in nanoseconds since the epoch
+    pub modified: u64,
+}
+
+impl SourceStamp {
+    /// Stamp a dump. Directories count every file directly inside them, and the directory
+    /// itself, so files being added or removed also change the stamp.
+    pub fn of<P: AsRef<Path>>(path: P) -> io::Result<SourceStamp> {
+        let path = path.as_ref();
+        let mut stamp = SourceStamp {
+            len: 0,
+            modified: 0,
+        };
+        let mut add = |metadata: fs::Metadata| -> io::Result<()> {
+            let modified = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            stamp.modified = stamp.modified.max(modified.as_nanos() as u64);
+            if metadata.is_file() {
+                stamp.len += metadata.len();
+            }
+            Ok(())
+        };
+        add(fs::metadata(path)?)?;
+        if path.is_dir() {
+            for entry in fs::read_dir(path)? {
+                let entry = entry?;
+                if entry.path().is_file() {
+                    add(entry.metadata()?)?;
+                }
+            }
+        }
+        Ok(stamp)
+    }
+}
+
+/// The dump a cache was built from, as it was at the time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceVersion {
+    pub stamp: SourceStamp,
+    /// hash of the dump's contents, see [`hash_source`]
+    pub hash: u64,
+}
+
+impl SourceVersion {
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<SourceVersion> {
+        Ok(SourceVersion {
+            stamp: SourceStamp::of(&path)?,
+            hash: hash_source(&path)?,
+        })
+    }
+}
+
+/// What a cache records in its metadata.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CacheMetadata {
+    pub key: CacheKey,
+    pub source: SourceVersion,
+    /// hash of the cached entries, see [`hash_entries`]
+    pub dataset_hash: u64,
+}
+
+/// What to do when the cache doesn't match the config.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StalePolicy {
+    /// throw the cache away and rebuild it from the dump
+    #[default]
+    Rebuild,
+    /// stop, so an expensive cache isn't thrown away by accident
+    Refuse,
+}
+
+/// The state of a cache on disk.
+pub enum CacheStatus {
+    Missing,
+    /// the cache was built from exactly what the config asks for
+    Fresh(MappedDataset, Box<CacheMetadata>),
+    /// the cache can't be used, with the reasons why
+    Stale(Vec<String>),
+}
+
+/// Open the cache at `path`, checking it against `wanted`.
+///
+/// The dump is only hashed when its size or modification time differ from when the cache was
+/// built, so opening a fresh cache doesn't read the whole dump.
+pub fn open<P: AsRef<Path>>(path: P, wanted: &CacheKey) -> CacheStatus {
+    if !path.as_ref().exists() {
+        return CacheStatus::Missing;
+    }
+    let dataset = match MappedDataset::open(path) {
+        Ok(dataset) => dataset,
+        Err(e) => return CacheStatus::Stale(vec![e.to_string()]),
+    };
+    let Ok(metadata) = serde_json::from_str::<CacheMetadata>(dataset.metadata()) else {
+        return CacheStatus::Stale(vec!["doesn't record what it was built from".to_string()]);
+    };
+    let differences = metadata.key.differences(wanted);
+    if !differences.is_empty() {
+        return CacheStatus::Stale(differences);
+    }
+    let unchanged = match SourceStamp::of(&wanted.source) {
+        Ok(stamp) if stamp == metadata.source.stamp => true,
+        // touched or copied, which doesn't have to mean the contents changed
+        Ok(_) => hash_source(&wanted.source).is_ok_and(|x| x == metadata.source.hash),
+        Err(_) => false,
+    };
+    if unchanged {
+        CacheStatus::Fresh(dataset, Box::new(metadata))
+    } else {
+        CacheStatus::Stale(vec![format!(
+            "{} changed since it was built",
+            wanted.source
+        )])
+    }
+}
+
+/// FNV-1a hash of a dataset's records, as they are laid out in the binary format.
+pub fn hash_entries(entries: &[ComputedEntry]) -> u64 {
+    let mut hash = Fnv::new();
+    for entry in entries {
+        hash.write(&binary::record(entry));
+    }
+    hash.0
+}
+
+/// FNV-1a hash of a dump's contents. Directories hash the names and contents of the files
+/// directly inside them, in sorted order, which is how they're imported.
+pub fn hash_source<P: AsRef<Path>>(path: P) -> io::Result<u64> {
+    let path = path.as_ref();
+    let mut hash = Fnv::new();
+    if path.is_dir() {
+        let mut files = vec![];
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            if entry.path().is_file() {
+                files.push(entry.path());
+            }
+        }
+        files.sort();
+        for file in files {
+            hash.write(file.file_name().unwrap().to_string_lossy().as_bytes());
+            hash.write_file(&file)?;
+        }
+    } else {
+        hash.write_file(path)?;
+    }
+    Ok(hash.0)
+}
+
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn write_file(&mut self, path: &Path) -> io::Result<()> {
+        let mut file = File::open(path)?;
+        let mut buffer = vec![0; 1 << 20];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                return Ok(());
+            }
+            self.write(&buffer[..read]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{binary, dataset::ComputedEntry};
+
+    use super::*;
+
+    #[test]
+    fn rejects_caches_built_from_something_else() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("dump.jsonl");
+        let cache = dir.path().join("database.bin");
+        fs::write(&source, "first").unwrap();
+        let source = source.to_str().unwrap();
+        let key = CacheKey::new(
+            source,
+            Format::CliOutput,
+            &Filter::new(),
+            Perspective::Recorded,
+            false,
+        );
+        let entries = [ComputedEntry {
+            input: Default::default(),
+            result: 1.0,
+            turns_until_end: 3,
+            game: 0,
+            turn: 5,
+            output: 0.0,
+        }];
+        let metadata = CacheMetadata {
+            key: key.clone(),
+            source: SourceVersion::read(source).unwrap(),
+            dataset_hash: hash_entries(&entries),
+        };
+        let write = |metadata: &CacheMetadata| {
+            let metadata = serde_json::to_string(metadata).unwrap();
+            binary::write(&cache, &AreaEval::FEATURES, &metadata, &entries).unwrap();
+        };
+        write(&metadata);
+        assert!(matches!(open(&cache, &key), CacheStatus::Fresh(_, x) if *x == metadata));
+
+        let filtered = CacheKey {
+            filter: Filter::new().min_turns(5),
+            ..key.clone()
+        };
+        assert!(matches!(open(&cache, &filtered), CacheStatus::Stale(x) if x.len() == 1));
+        let every = CacheKey {
+            perspective: Perspective::Every,
+            ..key.clone()
+        };
+        assert!(matches!(open(&cache, &every), CacheStatus::Stale(x) if x.len() == 1));
+
+        // a different stamp with the same contents only costs a hash
+        let mut touched = metadata.clone();
+        touched.source.stamp.modified += 1;
+        write(&touched);
+        assert!(matches!(open(&cache, &key), CacheStatus::Fresh(..)));
+        // while the same stamp is trusted without reading the dump
+        let mut trusted = metadata.clone();
+        trusted.source.hash += 1;
+        write(&trusted);
+        assert!(matches!(open(&cache, &key), CacheStatus::Fresh(..)));
+
+        fs::write(source, "second").unwrap();
+        assert!(matches!(open(&cache, &key), CacheStatus::Stale(x) if x.len() == 1));
+        assert!(matches!(
+            open(dir.path().join("missing.bin"), &key),
+            CacheStatus::Missing
+        ));
+    }
+}
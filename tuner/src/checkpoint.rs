@@ -0,0 +1,169 @@
+//! Saving training progress so long runs can be interrupted and resumed.
+use std::{fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{dataset::FEATURES, optimizer::ScheduledOptimizer, standardize::Standardization};
+
+/// Everything needed to carry on training where a run left off.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// [`Checkpoint::VERSION`] when written, checkpoints from before it had none
+    pub version: u32,
+    /// completed epochs
+    pub epoch: usize,
+    /// weights for the features as trained on, standardised if `standardization` is set
+    pub weights: [f64; FEATURES],
+    /// sigmoid scale folded into the starting weights, 1 if it wasn't fitted
+    pub scale: f64,
+    /// the transform applied to the features during training
+    pub standardization: Option<Standardization>,
+    /// the optimizer's settings and state, including the number of steps taken
+    pub optimizer: ScheduledOptimizer,
+    pub batch_size: usize,
+    /// seeds the split and the order of the training set every epoch
+    pub seed: u64,
+    pub early_stopping: EarlyStopping,
+}
+
+impl Checkpoint {
+    /// Bumped whenever a checkpoint can't be resumed by an older or newer tuner. Version 1
+    /// checkpoints predate the optimizer state, the sigmoid scale and the seed, and resuming
+    /// without them would silently train on a different split.
+    pub const VERSION: u32 = 2;
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Checkpoint> {
+        let value: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let version = match value.get("version") {
+            Some(version) => version.as_u64(),
+            None => Some(1),
+        };
+        if version != Some(Checkpoint::VERSION.into()) {
+            let version = version.map_or("unknown".to_string(), |x| x.to_string());
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "checkpoint version {version} can't be resumed by this tuner, which writes \
+                     version {}; start over without --resume",
+                    Checkpoint::VERSION
+                ),
+            ));
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Write the checkpoint to a temporary file first and move it into place, so an interrupted
+    /// write never destroys the previous checkpoint.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, serde_json::to_string_pretty(self)?)?;
+        fs::rename(temporary, path)
+    }
+}
+
+/// Tracks the best validation loss seen, stopping once it hasn't improved for `patience` epochs.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EarlyStopping {
+    /// `None` never stops early
+    pub patience: Option<usize>,
+    /// `None` until the first epoch has been measured
+    pub best_loss: Option<f64>,
+    pub best_weights: [f64; FEATURES],
+    pub best_epoch: usize,
+    pub epochs_without_improvement: usize,
+}
+
+impl EarlyStopping {
+    pub fn new(patience: Option<usize>, weights: [f64; FEATURES]) -> EarlyStopping {
+        EarlyStopping {
+            patience,
+            best_loss: None,
+            best_weights: weights,
+            best_epoch: 0,
+            epochs_without_improvement: 0,
+        }
+    }
+
+    /// Record the validation loss after `epoch`, returning whether training should stop.
+    pub fn update(&mut self, epoch: usize, loss: f64, weights: [f64; FEATURES]) -> bool {
+        if self.best_loss.is_none_or(|x| loss < x) {
+            self.best_loss = Some(loss);
+            self.best_weights = weights;
+            self.best_epoch = epoch;
+            self.epochs_without_improvement = 0;
+        } else {
+            self.epochs_without_improvement += 1;
+        }
+        self.patience
+            .is_some_and(|x| self.epochs_without_improvement >= x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::optimizer::Method;
+
+    use super::*;
+
+    #[test]
+    fn stops_after_patience_runs_out() {
+        let mut stopping = EarlyStopping::new(Some(2), [0.0; FEATURES]);
+        assert!(!stopping.update(1, 0.3, [1.0; FEATURES]));
+        assert!(!stopping.update(2, 0.2, [2.0; FEATURES]));
+        assert!(!stopping.update(3, 0.25, [3.0; FEATURES]));
+        assert!(stopping.update(4, 0.2, [4.0; FEATURES]));
+        assert_eq!(stopping.best_epoch, 2);
+        assert_eq!(stopping.best_weights, [2.0; FEATURES]);
+    }
+
+    #[test]
+    fn round_trips_through_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoint.json");
+        let checkpoint = Checkpoint {
+            version: Checkpoint::VERSION,
+            epoch: 3,
+            weights: [0.5, -0.25, 0.125, 1.0, 2.0, -3.0],
+            scale: 1.5,
+            standardization: None,
+            optimizer: ScheduledOptimizer {
+                method: Method::Momentum {
+                    momentum: 0.9,
+                    velocity: vec![0.5, 0.25, -0.125, 0.0, 1.0, 2.0],
+                },
+                step: 1200,
+                ..Default::default()
+            },
+            batch_size: 150,
+            seed: 12,
+            early_stopping: EarlyStopping::new(Some(5), [0.1; FEATURES]),
+        };
+        checkpoint.save(&path).unwrap();
+        assert_eq!(Checkpoint::load(&path).unwrap(), checkpoint);
+    }
+
+    #[test]
+    fn rejects_checkpoints_from_before_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoint.json");
+        // the layout written before the optimizer state, scale and seed were saved
+        let old = r#"{
+            "epoch": 3,
+            "step": 1200,
+            "weights": [0.5, -0.25, 0.125, 1.0, 2.0, -3.0],
+            "optimizer": { "learning_rate": 0.01 },
+            "early_stopping": {
+                "patience": 5,
+                "best_loss": null,
+                "best_weights": [0.1, 0.1, 0.1, 0.1, 0.1, 0.1],
+                "best_epoch": 0,
+                "epochs_without_improvement": 0
+            }
+        }"#;
+        fs::write(&path, old).unwrap();
+        let err = Checkpoint::load(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("checkpoint version 1"), "{err}");
+    }
+}
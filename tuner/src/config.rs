@@ -0,0 +1,242 @@
+//! The tuner's settings, read from a TOML file with overrides from the command line.
+use std::{fs, io, path::Path};
+
+use combat_adapter::{Filter, Format, Perspective};
+use eval::area_eval::AreaEval;
+use serde::Deserialize;
+use thiserror::Error;
+use toml::{Table, Value};
+
+use crate::{
+    cache::StalePolicy, nnue_trainer::NnueConfig, optimizer::ScheduledOptimizer,
+    selfplay::SelfPlayConfig, split::SplitConfig, spsa::SpsaConfig, td::TdConfig,
+};
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("unable to read config: {0}")]
+    Read(#[from] io::Error),
+    #[error("config was not well-formatted: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("override `{0}` should look like key=value")]
+    Override(String),
+    #[error("unknown feature `{0}`")]
+    UnknownFeature(String),
+    #[error("the filter has to keep to duels, the only games the evaluation handles")]
+    NotDuels,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Config {
+    pub weights: [f64; 6],
+    pub db_path: String,
+    /// what kind of games `db_path` points at
+    #[serde(default)]
+    pub format: Format,
+    /// which games and turns to extract from the dump, keys left out keep their defaults of
+    /// duels that last at least 21 turns
+    #[serde(default = "default_filter")]
+    pub filter: Filter,
+    /// which snakes every position is seen from, `every` adds one copy per living snake
+    #[serde(default)]
+    pub perspective: Perspective,
+    /// add all 8 rotations / reflections of every position to the dataset
+    #[serde(default)]
+    pub augment_symmetries: bool,
+    /// where the computed features are cached
+    #[serde(default = "default_cache")]
+    pub cache: String,
+    /// what to do when the cache was built from a different dump, filter or feature set
+    #[serde(default)]
+    pub on_stale_cache: StalePolicy,
+    /// blend the result towards a draw by this factor per turn before the end of the game
+    pub discount: Option<f64>,
+    /// how the games are divided between training, validation and test
+    #[serde(default)]
+    pub split: SplitConfig,
+    /// passes over the training set
+    #[serde(default = "default_epochs")]
+    pub epochs: usize,
+    /// train on features standardised with the training set's mean and standard deviation
+    #[serde(default)]
+    pub standardize: bool,
+    /// fit the scale of the sigmoid to the training set before optimising the weights
+    #[serde(default)]
+    pub fit_scale: bool,
+    /// stop once the validation loss hasn't improved for this many epochs
+    pub patience: Option<usize>,
+    /// where progress is saved after every epoch
+    #[serde(default = "default_checkpoint")]
+    pub checkpoint: String,
+    /// carry on from `checkpoint` instead of starting from `weights`
+    #[serde(default)]
+    pub resume: bool,
+    /// update rule, learning rate and schedule
+    #[serde(default)]
+    pub optimizer: ScheduledOptimizer,
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// features the weights may use, the others are zeroed out, all of them when it's missing
+    pub features: Option<Vec<String>>,
+    /// don't print progress while training, as when a sweep runs several trainings at once
+    #[serde(default)]
+    pub quiet: bool,
+    /// seeds the split and the shuffling of the training set, one is picked when it's missing
+    pub seed: Option<u64>,
+    /// where the tuned weights are written
+    #[serde(default = "default_output")]
+    pub output: String,
+    /// where `tuner report` writes its CSV files
+    #[serde(default = "default_report_dir")]
+    pub report_dir: String,
+    pub nnue: Option<NnueConfig>,
+    /// how games are played when tuning by self-play
+    #[serde(default)]
+    pub self_play: SelfPlayConfig,
+    #[serde(default)]
+    pub spsa: SpsaConfig,
+    /// TD-leaf, which trains with `optimizer` and `batch_size` on its replay buffer
+    #[serde(default)]
+    pub td: TdConfig,
+}
+
+fn default_cache() -> String {
+    "database.bin".to_string()
+}
+
+fn default_epochs() -> usize {
+    10
+}
+
+fn default_batch_size() -> usize {
+    150
+}
+
+fn default_checkpoint() -> String {
+    "checkpoint.json".to_string()
+}
+
+fn default_output() -> String {
+    "weights.toml".to_string()
+}
+
+fn default_report_dir() -> String {
+    "report".to_string()
+}
+
+// the evaluation only handles duels, and short games are mostly noise
+fn default_filter() -> Filter {
+    Filter::new().snakes(2..=2).min_turns(21)
+}
+
+impl Config {
+    /// Read the config at `path`, then apply `key=value` overrides on top of it.
+    pub fn load<P: AsRef<Path>>(path: P, overrides: &[String]) -> Result<Config, ConfigError> {
+        Self::parse(&fs::read_to_string(path)?, overrides)
+    }
+
+    /// Parse a config, then apply `key=value` overrides on top of it.
+    ///
+    /// Keys can be nested with dots, like `optimizer.learning_rate=0.1`. Values are read as
+    /// TOML, falling back to a plain string, so `format=cli_output` works without quotes.
+    pub fn parse(text: &str, overrides: &[String]) -> Result<Config, ConfigError> {
+        let mut table: Table = toml::from_str(text)?;
+        for assignment in overrides {
+            let (key, value) = assignment
+                .split_once('=')
+                .ok_or_else(|| ConfigError::Override(assignment.clone()))?;
+            let value = toml::from_str::<Table>(&format!("value = {value}"))
+                .ok()
+                .and_then(|mut x| x.remove("value"))
+                .unwrap_or_else(|| Value::String(value.to_string()));
+            set(&mut table, key.trim(), value)
+                .map_err(|_| ConfigError::Override(assignment.clone()))?;
+        }
+        // a partial filter only changes the keys it sets, the rest keep their defaults
+        if let Some(Value::Table(filter)) = table.get_mut("filter") {
+            let Ok(Value::Table(mut merged)) = Value::try_from(default_filter()) else {
+                unreachable!("a filter is a table");
+            };
+            merged.extend(std::mem::take(filter));
+            *filter = merged;
+        }
+        let config: Config = Table::try_into(table)?;
+        if config.filter.min_snakes != Some(2) || config.filter.max_snakes != Some(2) {
+            return Err(ConfigError::NotDuels);
+        }
+        for feature in config.features.iter().flatten() {
+            if !AreaEval::FEATURES.contains(&feature.as_str()) {
+                return Err(ConfigError::UnknownFeature(feature.clone()));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Whether the weights may use `feature`.
+    pub fn uses(&self, feature: &str) -> bool {
+        self.features
+            .as_ref()
+            .is_none_or(|x| x.iter().any(|x| x == feature))
+    }
+}
+
+// set a dotted key, creating tables on the way
+fn set(table: &mut Table, key: &str, value: Value) -> Result<(), ()> {
+    match key.split_once('.') {
+        Some((head, rest)) => {
+            let inner = table
+                .entry(head)
+                .or_insert_with(|| Value::Table(Table::new()));
+            match inner {
+                Value::Table(inner) => set(inner, rest, value),
+                _ => Err(()),
+            }
+        }
+        None => {
+            table.insert(key.to_string(), value);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::optimizer::Method;
+
+    use super::*;
+
+    #[test]
+    fn overrides_nested_keys() {
+        let text = "weights = [0.0, 0.0, 0.0, 0.0, 0.0, 0.0]\ndb_path = \"games.db\"\n";
+        let overrides = [
+            "optimizer.learning_rate=0.5",
+            "optimizer.method.kind=adam",
+            "format=cli_output",
+            "filter.min_turns=5",
+            "perspective=every",
+            "epochs = 3",
+        ]
+        .map(String::from);
+        let config = Config::parse(text, &overrides).unwrap();
+        assert_eq!(config.optimizer.learning_rate, 0.5);
+        assert!(matches!(config.optimizer.method, Method::Adam { .. }));
+        assert_eq!(config.format, Format::CliOutput);
+        assert_eq!(config.filter, Filter::new().snakes(2..=2).min_turns(5));
+        assert_eq!(config.perspective, Perspective::Every);
+        assert_eq!(config.epochs, 3);
+        assert!(Config::parse(text, &["epochs".to_string()]).is_err());
+        let table = format!("{text}[filter]\nruleset = \"standard\"\n");
+        let config = Config::parse(&table, &[]).unwrap();
+        assert_eq!(config.filter, default_filter().ruleset("standard"));
+        let free_for_all = ["filter.max_snakes=4".to_string()];
+        assert!(matches!(
+            Config::parse(text, &free_for_all),
+            Err(ConfigError::NotDuels)
+        ));
+        let features = ["features=[\"bias\", \"length_difference\"]".to_string()];
+        let config = Config::parse(text, &features).unwrap();
+        assert!(config.uses("bias") && !config.uses("health_difference"));
+        let features = ["features=[\"colour\"]".to_string()];
+        assert!(Config::parse(text, &features).is_err());
+    }
+}
@@ -0,0 +1,52 @@
+//! Precomputed training data.
+use nalgebra::SVector;
+use serde::{Deserialize, Serialize};
+use snake_tuner::database::{Database, Entry};
+
+/// Number of features produced by [`eval::area_eval::AreaEval::label`].
+pub const FEATURES: usize = 6;
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct DB {
+    pub entries: Vec<ComputedEntry>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct ComputedEntry {
+    pub input: SVector<f64, FEATURES>,
+    /// 1 for a win, 0 for a loss, 0.5 for a draw
+    pub result: f64,
+    pub turns_until_end: u32,
+    /// index of the game the position came from, so games can be kept within one split
+    pub game: u32,
+    /// [`ComputedEntry::UNKNOWN_TURN`] for positions from caches that didn't store it
+    pub turn: u32,
+    /// training target, filled in from the result once the discount is known
+    #[serde(skip)]
+    pub output: f64,
+}
+
+impl ComputedEntry {
+    /// The turn of a position whose turn wasn't recorded.
+    pub const UNKNOWN_TURN: u32 = u32::MAX;
+}
+
+impl Entry<FEATURES> for ComputedEntry {
+    fn get_inputs(&self) -> SVector<f64, FEATURES> {
+        self.input
+    }
+
+    fn get_expected_output(&self) -> f64 {
+        self.output
+    }
+}
+
+impl Database<FEATURES, ComputedEntry> for DB {
+    fn size(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn get(&self, idx: usize) -> ComputedEntry {
+        self.entries[idx]
+    }
+}
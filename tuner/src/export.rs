@@ -0,0 +1,90 @@
+//! Writing tuned weights in a form the engine can load.
+use std::{fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dataset::FEATURES, metrics::Metrics, optimizer::ScheduledOptimizer,
+    standardize::Standardization,
+};
+
+/// A weights file, loadable with `AreaEval::load`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WeightsFile {
+    /// in the same order as `Config.weights`
+    pub weights: [f64; FEATURES],
+    pub metadata: Metadata,
+}
+
+/// Where a set of weights came from and how well they did.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// name of each weight's feature
+    pub features: Vec<String>,
+    /// hash of the dataset the weights were trained on, as hex
+    pub dataset_hash: String,
+    /// seed of the run, which picked the split and the order of the training set
+    pub seed: u64,
+    /// sigmoid scale fitted to the dataset and folded into the weights, 1 if it wasn't fitted
+    pub scale: f64,
+    /// the standardisation used during training, already folded into the weights
+    pub standardization: Option<Standardization>,
+    pub batch_size: usize,
+    /// optimizer settings, and the number of steps taken
+    pub optimizer: ScheduledOptimizer,
+    pub train: Metrics,
+    pub validation: Metrics,
+    pub test: Metrics,
+}
+
+impl WeightsFile {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<WeightsFile> {
+        toml::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let text =
+            toml::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eval::area_eval::AreaEval;
+
+    use super::*;
+
+    #[test]
+    fn area_eval_loads_exported_weights() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("weights.toml");
+        let metrics = Metrics {
+            loss: 0.2,
+            log_loss: 0.6,
+            accuracy: 0.7,
+        };
+        let file = WeightsFile {
+            weights: [0.1, -0.2, 0.3, -0.4, 0.5, -0.6],
+            metadata: Metadata {
+                features: AreaEval::FEATURES.map(String::from).to_vec(),
+                dataset_hash: format!("{:016x}", 0xdead_beef_u64),
+                seed: 12,
+                scale: 1.0,
+                standardization: Some(Standardization {
+                    mean: [0.1, 0.2, 0.3, 0.4, 0.5, 1.0],
+                    std: [1.0, 2.0, 3.0, 4.0, 5.0, 0.0],
+                }),
+                batch_size: 150,
+                optimizer: ScheduledOptimizer::default(),
+                train: metrics,
+                validation: metrics,
+                test: metrics,
+            },
+        };
+        file.save(&path).unwrap();
+        assert_eq!(WeightsFile::load(&path).unwrap(), file);
+        assert_eq!(AreaEval::load(&path).unwrap().weights(), file.weights);
+    }
+}
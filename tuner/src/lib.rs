@@ -1,3 +1,35 @@
+pub mod binary;
+pub mod cache;
+pub mod checkpoint;
+pub mod config;
+pub mod dataset;
+pub mod export;
+pub mod metrics;
+pub mod nnue_trainer;
+pub mod objective;
+pub mod optimizer;
+pub mod report;
+pub mod selfplay;
+pub mod split;
+pub mod spsa;
+pub mod standardize;
+pub mod sweep;
+pub mod td;
+pub mod training;
+
+/// Training target for a position.
+///
+/// Without a discount this is just the game result. With one, the result is blended towards a
+/// draw the further the position is from the end of the game, so early positions aren't
+/// expected to predict the result as confidently as the final moves:
+/// `0.5 + (result - 0.5) * discount ^ turns_until_end`.
+pub fn target(result: f64, turns_until_end: u32, discount: Option<f64>) -> f64 {
+    match discount {
+        Some(discount) => 0.5 + (result - 0.5) * discount.powi(turns_until_end as i32),
+        None => result,
+    }
+}
+
 pub fn add(left: usize, right: usize) -> usize {
     left + right
 }
@@ -1,53 +1,113 @@
 use std::{
     fs,
-    path::Path,
-    sync::{Arc, Mutex},
-    time::{Duration, Instant},
+    path::{Path, PathBuf},
+    process,
+    time::Instant,
 };
 
-use eval::area_eval::AreaEval;
-use nalgebra::SVector;
-use pretty_assertions::{assert_eq, assert_ne};
+use board::symmetry::Symmetry;
+use clap::{Parser, Subcommand};
+use combat_adapter::{SkipErrors, SkipReport};
+use eval::{
+    area_eval::AreaEval,
+    nnue::{check_board, BOARD_WIDTH},
+};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
 use rayon::prelude::*;
-use serde::{Deserialize, Serialize};
-use snake_tuner::{
-    activation::{functions::Sigmoid, ActivationFunction},
-    database::{self, Database, Entry},
-    dataloader::DataLoader,
-    evaluation::{evaluations::Linear, Eval},
-    optimizer::{optimizers::SGD, Optimizer},
+use tuner::{
+    binary,
+    cache::{self, CacheKey, CacheMetadata, CacheStatus, SourceVersion, StalePolicy},
+    config::Config,
+    dataset::{ComputedEntry, DB},
+    export::WeightsFile,
+    metrics::Metrics,
+    nnue_trainer::{NnueTrainer, ShuffledBatches},
+    report::Report,
+    split::Split,
+    spsa::{Spsa, SpsaFile},
+    sweep::{self, Summary, SweepSpec},
+    target,
+    td::{ReplayBuffer, Td, TdFile},
+    training,
 };
-#[derive(Deserialize, Serialize, Clone)]
-struct DB {
-    entries: Vec<ComputedEntry>,
-}
-#[derive(Deserialize, Serialize, Clone, Copy)]
-struct ComputedEntry {
-    input: SVector<f64, 6>,
-    output: f64,
-}
-impl Entry<6> for ComputedEntry {
-    fn get_inputs(&self) -> SVector<f64, 6> {
-        self.input
-    }
 
-    fn get_expected_output(&self) -> f64 {
-        self.output
-    }
+#[derive(Parser)]
+#[command(about = "Tunes the weights of the area evaluation")]
+struct Cli {
+    #[arg(long, default_value = "config.toml")]
+    config: PathBuf,
+    /// override a config key, like `--set optimizer.learning_rate=0.1`
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
+    /// where the computed features are cached
+    #[arg(long)]
+    cache: Option<String>,
+    /// seed for the split and for shuffling the training set
+    #[arg(long)]
+    seed: Option<u64>,
+    #[command(subcommand)]
+    command: Command,
 }
-impl Database<6, ComputedEntry> for DB {
-    fn size(&self) -> usize {
-        self.entries.len()
-    }
 
-    fn get(&self, idx: usize) -> ComputedEntry {
-        self.entries[idx]
-    }
-}
-#[derive(Deserialize)]
-struct Config {
-    weights: [f64; 6],
-    db_path: String,
+#[derive(Subcommand)]
+enum Command {
+    /// Compute the features of every position and cache them
+    BuildDataset {
+        /// convert a `database.json` cache from an older tuner instead of reading the dump
+        #[arg(long)]
+        from_json: Option<PathBuf>,
+    },
+    /// Tune the weights and write them out
+    Train {
+        /// where the tuned weights are written
+        #[arg(long)]
+        output: Option<String>,
+        #[arg(long)]
+        checkpoint: Option<String>,
+        /// carry on from the checkpoint
+        #[arg(long)]
+        resume: bool,
+    },
+    /// Measure a weights file on the training, validation and test sets
+    Evaluate {
+        #[arg(long)]
+        weights: Option<String>,
+    },
+    /// Calibration, feature ablation and accuracy by turn on the test set
+    Report {
+        #[arg(long)]
+        weights: Option<String>,
+        /// directory the CSV files are written to
+        #[arg(long)]
+        out: Option<String>,
+    },
+    /// Tune the weights by self-play with SPSA, starting from the config's weights
+    Spsa {
+        /// start from this weights file instead
+        #[arg(long)]
+        weights: Option<String>,
+        /// where the tuned weights are written
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Train with each combination of settings in a sweep spec and rank them
+    Sweep {
+        #[arg(long, default_value = "sweep.toml")]
+        spec: PathBuf,
+        /// directory the trials and summary are written to
+        #[arg(long)]
+        out: Option<String>,
+    },
+    /// Learn the weights from self-play with TD-leaf, starting from the config's weights
+    Td {
+        /// start from this weights file instead
+        #[arg(long)]
+        weights: Option<String>,
+        /// where the tuned weights are written
+        #[arg(long)]
+        output: Option<String>,
+    },
 }
 
 fn main() {
@@ -59,70 +119,404 @@ fn main() {
     //     0.00470536898375267,
     // ];
 
-    let config = fs::read_to_string("config.toml").expect("Unable to read file");
-    let config: Config = toml::from_str(&config).expect("Config was not well-formatted");
-    let mut eval = AreaEval {
-        eval: Linear::<6, Sigmoid>::from_weights(SVector::from(config.weights), Sigmoid),
+    let cli = Cli::parse();
+    let mut config = match Config::load(&cli.config, &cli.overrides) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}: {e}", cli.config.display());
+            process::exit(1);
+        }
     };
+    // flags win over both the file and `--set`
+    if let Some(cache) = cli.cache {
+        config.cache = cache;
+    }
+    if cli.seed.is_some() {
+        config.seed = cli.seed;
+    }
+    match cli.command {
+        Command::BuildDataset { from_json } => {
+            if let Some(json) = from_json {
+                convert_json(&config, &json);
+            }
+            let (database, _) = load_dataset(&config);
+            println!("{} entries in {}", database.entries.len(), config.cache);
+        }
+        Command::Train {
+            output,
+            checkpoint,
+            resume,
+        } => {
+            config.output = output.unwrap_or(config.output);
+            config.checkpoint = checkpoint.unwrap_or(config.checkpoint);
+            config.resume |= resume;
+            train(config);
+        }
+        Command::Evaluate { weights } => {
+            config.output = weights.unwrap_or(config.output);
+            evaluate(&config);
+        }
+        Command::Report { weights, out } => {
+            config.output = weights.unwrap_or(config.output);
+            config.report_dir = out.unwrap_or(config.report_dir);
+            report(&config);
+        }
+        Command::Spsa { weights, output } => {
+            if let Some(weights) = weights {
+                config.weights = AreaEval::load(weights)
+                    .expect("Unable to read weights")
+                    .weights();
+            }
+            config.spsa.output = output.unwrap_or(config.spsa.output);
+            spsa(config);
+        }
+        Command::Sweep { spec, out } => {
+            let mut spec = match SweepSpec::load(&spec) {
+                Ok(x) => x,
+                Err(e) => {
+                    eprintln!("{}: {e}", spec.display());
+                    process::exit(1);
+                }
+            };
+            spec.directory = out.unwrap_or(spec.directory);
+            // trials start from the same file and overrides as this run
+            let base = fs::read_to_string(&cli.config).expect("Unable to read config");
+            sweep(&base, cli.overrides, &config, &spec);
+        }
+        Command::Td { weights, output } => {
+            if let Some(weights) = weights {
+                config.weights = AreaEval::load(weights)
+                    .expect("Unable to read weights")
+                    .weights();
+            }
+            config.td.output = output.unwrap_or(config.td.output);
+            td(config);
+        }
+    }
+}
+
+// what the cache has to have been built from for the config
+fn cache_key(config: &Config) -> CacheKey {
+    CacheKey::new(
+        &config.db_path,
+        config.format,
+        &config.filter,
+        config.perspective,
+        config.augment_symmetries,
+    )
+}
+
+/// Load the cached dataset, rebuilding it from the dump if needed, with training targets set.
+///
+/// Also returns the dataset's hash, as hex, for the metadata of weights trained on it.
+fn load_dataset(config: &Config) -> (DB, String) {
     println!("Opening DB");
-    let database;
-    if Path::new("database.json").exists() {
+    let mut database: DB;
+    let dataset_hash;
+    let key = cache_key(config);
+    let cached = match cache::open(&config.cache, &key) {
+        CacheStatus::Fresh(dataset, metadata) => Some((dataset, metadata)),
+        CacheStatus::Missing => None,
+        CacheStatus::Stale(reasons) => {
+            println!("{} is out of date:", config.cache);
+            for reason in reasons {
+                println!("    {reason}");
+            }
+            if config.on_stale_cache == StalePolicy::Refuse {
+                eprintln!("refusing to rebuild it, delete it or set on_stale_cache = \"rebuild\"");
+                process::exit(1);
+            }
+            None
+        }
+    };
+    if let Some((dataset, metadata)) = cached {
         println!("found old");
-        let db = fs::read_to_string("database.json").unwrap();
-        database = serde_json::from_str(&db).unwrap();
+        // training rewrites the entries, so they're decoded rather than read from the mapping
+        database = dataset.to_db();
+        dataset_hash = metadata.dataset_hash;
     } else {
         println!("scanning in from sql");
-        let db = combat_adapter::DB::new(config.db_path, 2);
-        let database_bar = Arc::new(Mutex::new(pbr::ProgressBar::new(db.positions.len() as u64)));
-        let o_bar = database_bar.clone();
+        let source =
+            SourceVersion::read(&config.db_path).expect("Unable to read the game database");
+        // stream the dump one game at a time so it never has to fit in memory
+        let mut games = SkipErrors::new(
+            combat_adapter::stream(
+                &config.db_path,
+                config.format,
+                config.filter.clone(),
+                config.perspective,
+            )
+            .expect("Unable to open the game database"),
+        );
+        let mut database_bar = pbr::ProgressBar::new(games.size_hint().1.unwrap() as u64);
         let t0 = Instant::now();
-        let entries = db
-            .positions
-            .par_iter()
-            .map(move |(x, y)| {
+        let mut entries = vec![];
+        for (game, mut positions) in games.by_ref().enumerate() {
+            if config.augment_symmetries {
+                positions = positions
+                    .iter()
+                    .flat_map(|(x, y)| Symmetry::ALL.map(|s| (x.transformed(s), *y)))
+                    .collect();
+            }
+            // extending from an indexed parallel iterator keeps the positions in order
+            entries.par_extend(positions.par_iter().map(|(x, y)| {
                 let input = AreaEval::label(x);
-                let output = if &x.you_id == y { 1.0 } else { 0.0 };
-                database_bar.lock().unwrap().inc();
-                ComputedEntry { input, output }
-            })
-            .collect();
-        o_bar.lock().unwrap().finish();
+                ComputedEntry {
+                    input,
+                    result: y.outcome.result(),
+                    turns_until_end: y.turns_until_end,
+                    game: game as u32,
+                    turn: x.turn,
+                    output: 0.0,
+                }
+            }));
+            database_bar.inc();
+        }
+        database_bar.finish();
+        report_skipped(&games.report);
         println!("{:?}", t0.elapsed());
+        dataset_hash = cache::hash_entries(&entries);
+        let metadata = CacheMetadata {
+            key,
+            source,
+            dataset_hash,
+        };
+        let metadata = serde_json::to_string(&metadata).unwrap();
+        binary::write(&config.cache, &AreaEval::FEATURES, &metadata, &entries).unwrap();
         database = DB { entries };
-        let x = serde_json::to_string(&database).unwrap();
-        fs::write("database.json", x).unwrap();
     }
-    println!("{:?}", database.entries.len());
-    let mut dataloader = DataLoader::new(database.clone(), 150, true);
-    let mut grad = SGD::new(0.01);
-    let mut sum = 0.001;
-
-    for entry in &database.entries {
-        let error = (entry.output
-            - eval
-                .eval
-                .activation_fn()
-                .evaluate(eval.eval.forward(entry.input)))
-        .powf(2.0);
-        sum += error;
+    for entry in &mut database.entries {
+        entry.output = target(entry.result, entry.turns_until_end, config.discount);
+    }
+    (database, format!("{dataset_hash:016x}"))
+}
+
+/// Turn a JSON cache into a binary one for the config's dump, filter and features.
+///
+/// JSON caches don't record what they were built from, so this takes the config's word for it.
+fn convert_json(config: &Config, json: &Path) {
+    println!("converting {}", json.display());
+    let entries = binary::read_json(json).expect("Unable to read the JSON cache");
+    let metadata = CacheMetadata {
+        key: cache_key(config),
+        source: SourceVersion::read(&config.db_path).expect("Unable to read the game database"),
+        dataset_hash: cache::hash_entries(&entries),
+    };
+    let metadata = serde_json::to_string(&metadata).unwrap();
+    binary::write(&config.cache, &AreaEval::FEATURES, &metadata, &entries)
+        .expect("Unable to write the cache");
+    println!(
+        "{} entries from {} games",
+        entries.len(),
+        entries.last().map_or(0, |x| x.game + 1)
+    );
+}
+
+/// Measure how the exported weights do on each set.
+fn evaluate(config: &Config) {
+    let weights = WeightsFile::load(&config.output).expect("Unable to read weights");
+    let eval = AreaEval::new(weights.weights);
+    let (database, _) = load_dataset(config);
+    let split = Split::by_game(&database.entries, config.split, weights.metadata.seed);
+    for (name, db) in [
+        ("train", &split.train),
+        ("validation", &split.validation),
+        ("test", &split.test),
+    ] {
+        println!(
+            "{name:>10} ({} entries): {}",
+            db.entries.len(),
+            Metrics::measure(&eval.eval, &db.entries)
+        );
+    }
+}
+
+/// Break down how the exported weights do on the test set.
+fn report(config: &Config) {
+    let weights = WeightsFile::load(&config.output).expect("Unable to read weights");
+    let eval = AreaEval::new(weights.weights);
+    let (database, _) = load_dataset(config);
+    let split = Split::by_game(&database.entries, config.split, weights.metadata.seed);
+    let report = Report::new(&eval.eval, &AreaEval::FEATURES, &split.test.entries);
+    println!("{report}");
+    report
+        .write_csv(&config.report_dir)
+        .expect("Unable to write report");
+    println!("written to {}", config.report_dir);
+}
+
+/// Tune the weights by playing perturbed copies of them against each other.
+fn spsa(config: Config) {
+    let seed = config.seed.unwrap_or_else(|| rand::random::<u32>().into());
+    println!("seed {seed}");
+    let output = config.spsa.output.clone();
+    let mut spsa = Spsa::new(config.spsa, config.self_play, config.weights, seed);
+    while spsa.iteration < spsa.config.iterations {
+        let t0 = Instant::now();
+        let iteration = spsa.step();
+        println!(
+            "iteration {} ({:.1?}): score {:.3}, a_k {:.4}, c_k {:.4}, {}, weights {:?}",
+            spsa.iteration,
+            t0.elapsed(),
+            iteration.score,
+            iteration.step_size,
+            iteration.perturbation,
+            iteration.search,
+            spsa.weights
+        );
+        SpsaFile::new(&spsa)
+            .save(&output)
+            .expect("Unable to write weights");
+    }
+    println!("weights = {:?}, written to {output}", spsa.weights);
+}
+
+/// Train once per trial of `spec` and rank the results.
+fn sweep(base: &str, mut overrides: Vec<String>, config: &Config, spec: &SweepSpec) {
+    // every trial gets the same split, unless the sweep varies the seed itself
+    let seed = config.seed.unwrap_or_else(|| rand::random::<u32>().into());
+    println!("seed {seed}");
+    overrides.push(format!("seed={seed}"));
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let configs = spec
+        .assignments(&mut rng)
+        .and_then(|x| Ok((sweep::configs(base, &overrides, &x)?, x)));
+    let (configs, assignments) = match configs {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    };
+    let (database, dataset_hash) = load_dataset(config);
+    println!("running {} trials", configs.len());
+    let trials = sweep::run(spec, assignments, configs, &database, &dataset_hash)
+        .expect("Unable to write trial results");
+    let summary = Summary::new(spec, config.optimizer.objective, trials);
+    println!("{summary}");
+    let path = Path::new(&spec.directory).join("summary.csv");
+    summary.write_csv(&path).expect("Unable to write summary");
+    println!("written to {}", path.display());
+}
+
+/// Learn the weights by playing them against themselves and fitting later evaluations.
+fn td(config: Config) {
+    let seed = config.seed.unwrap_or_else(|| rand::random::<u32>().into());
+    println!("seed {seed}");
+    let buffer = ReplayBuffer::load(&config.td.buffer).expect("Unable to read the replay buffer");
+    println!("{} positions in {}", buffer.entries.len(), config.td.buffer);
+    let (buffer_path, output) = (config.td.buffer.clone(), config.td.output.clone());
+    let mut td = Td::new(
+        config.td,
+        config.self_play,
+        config.optimizer,
+        config.batch_size,
+        config.weights,
+        buffer,
+        seed,
+    );
+    while td.iteration < td.config.iterations {
+        let t0 = Instant::now();
+        let iteration = td.step();
+        println!(
+            "iteration {} ({:.1?}): {} new positions, score {:.3}, {}, buffer {} | {}",
+            td.iteration,
+            t0.elapsed(),
+            iteration.positions,
+            iteration.score,
+            iteration.search,
+            td.buffer.entries.len(),
+            iteration.metrics
+        );
+        td.buffer
+            .save(&buffer_path)
+            .expect("Unable to write the replay buffer");
+        TdFile::new(&td)
+            .save(&output)
+            .expect("Unable to write weights");
     }
-    println!("{}", sum / database.entries.len() as f64);
-    for x in 0..100000 {
-        // println!("{x}");
-        // println!("{:?}", eval.eval);
-        grad.step(&mut eval.eval, dataloader.sample());
+    println!("weights = {:?}, written to {output}", td.eval.weights());
+}
+
+// list the records that couldn't be imported, so a bad dump doesn't go unnoticed
+fn report_skipped(report: &SkipReport) {
+    if report.count() > 0 {
+        println!("skipped {} bad records:", report.count());
+        for err in &report.skipped {
+            println!("  {err}");
+        }
     }
-    let mut sum = 0.0;
-    for entry in &database.entries {
-        let error = (entry.output
-            - eval
-                .eval
-                .activation_fn()
-                .evaluate(eval.eval.forward(entry.input)))
-        .powf(2.0);
-        sum += error;
+}
+
+fn train(config: Config) {
+    let (database, dataset_hash) = load_dataset(&config);
+    let weights = training::train(&config, &database, dataset_hash);
+    weights
+        .save(&config.output)
+        .expect("Unable to write weights");
+    println!(
+        "weights = {:?}, written to {}",
+        weights.weights, config.output
+    );
+
+    if let Some(nnue) = config.nnue {
+        println!("training nnue");
+        let mut rng = ChaCha8Rng::seed_from_u64(weights.metadata.seed);
+        let mut trainer = NnueTrainer::new(nnue.hidden, nnue.learning_rate, &mut rng);
+        let mut step = 0;
+        // stream the dump through a bounded shuffle buffer, passing over it again until done
+        while step < nnue.steps {
+            let mut games = SkipErrors::new(
+                combat_adapter::stream(
+                    &config.db_path,
+                    config.format,
+                    config.filter.clone(),
+                    config.perspective,
+                )
+                .expect("Unable to open the game database"),
+            );
+            // the net only has inputs for the squares of the largest board it supports
+            let mut unsupported = 0;
+            let positions = games
+                .by_ref()
+                .flatten()
+                .filter(|(x, _)| {
+                    let supported = check_board(&x.board).is_ok();
+                    unsupported += !supported as usize;
+                    supported
+                })
+                .map(|(x, y)| {
+                    let output = target(y.outcome.result(), y.turns_until_end, config.discount);
+                    (x, output)
+                });
+            let start = step;
+            for batch in
+                ShuffledBatches::new(positions, nnue.shuffle_buffer, nnue.batch_size, &mut rng)
+            {
+                let loss = trainer
+                    .step(&batch)
+                    .expect("unsupported boards are filtered out");
+                if step % 1000 == 0 {
+                    println!("step {step}: {loss}");
+                }
+                step += 1;
+                if step == nnue.steps {
+                    break;
+                }
+            }
+            report_skipped(&games.report);
+            if unsupported > 0 {
+                eprintln!(
+                    "skipped {unsupported} positions on boards larger than {BOARD_WIDTH}x{BOARD_WIDTH}"
+                );
+            }
+            if step == start {
+                eprintln!("no positions to train the nnue on");
+                process::exit(1);
+            }
+        }
+        trainer.net.save(&nnue.output).unwrap();
     }
-    println!("{}", sum / database.entries.len() as f64);
     // // let mut io = vec![];
     // println!("Starting iteration loop");
     // let mut accum = 0.0;
@@ -0,0 +1,61 @@
+//! How well a set of weights fits a dataset.
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use snake_tuner::{
+    activation::{functions::Sigmoid, ActivationFunction},
+    evaluation::{evaluations::Linear, Eval},
+};
+
+use crate::{
+    dataset::{ComputedEntry, FEATURES},
+    objective::Objective,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Metrics {
+    /// mean squared error against the training target
+    pub loss: f64,
+    /// cross-entropy against the training target
+    pub log_loss: f64,
+    /// fraction of decisive positions where the predicted winner won, draws are left out
+    pub accuracy: f64,
+}
+
+impl Metrics {
+    pub fn measure(eval: &Linear<FEATURES, Sigmoid>, entries: &[ComputedEntry]) -> Metrics {
+        let mut loss = 0.0;
+        let mut log_loss = 0.0;
+        let mut correct = 0;
+        let mut decisive = 0;
+        for entry in entries {
+            let prediction = eval.activation_fn().evaluate(eval.forward(entry.input));
+            loss += Objective::SquaredError.loss(prediction, entry.output);
+            log_loss += Objective::LogLoss.loss(prediction, entry.output);
+            if entry.result != 0.5 {
+                decisive += 1;
+                if (prediction > 0.5) == (entry.result > 0.5) {
+                    correct += 1;
+                }
+            }
+        }
+        let count = entries.len().max(1) as f64;
+        Metrics {
+            loss: loss / count,
+            log_loss: log_loss / count,
+            accuracy: correct as f64 / decisive.max(1) as f64,
+        }
+    }
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "loss {:.6}, log-loss {:.6}, accuracy {:.2}%",
+            self.loss,
+            self.log_loss,
+            self.accuracy * 100.0
+        )
+    }
+}
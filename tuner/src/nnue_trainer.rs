@@ -0,0 +1,196 @@
+//! Trainer for the [`eval::nnue`] evaluator.
+//!
+//! Plain minibatch gradient descent on the squared error between the network output and the
+//! game result, backpropagated through the sigmoid, the output layer and the clipped ReLU.
+//! Only the columns of the active inputs receive a first layer gradient.
+//!
+//! Positions are streamed from the dump through a bounded [`ShuffledBatches`] buffer, so the
+//! dump never has to fit in memory.
+use std::collections::HashMap;
+
+use board::useful_board::Game;
+use eval::nnue::{active_features, clipped_relu, Nnue, UnsupportedBoard};
+use rand::{seq::SliceRandom, Rng};
+use serde::Deserialize;
+
+/// `[nnue]` section of the tuner config.
+#[derive(Deserialize, Clone, Debug)]
+pub struct NnueConfig {
+    /// where to write the trained weights
+    pub output: String,
+    pub hidden: usize,
+    pub steps: usize,
+    pub learning_rate: f32,
+    pub batch_size: usize,
+    /// positions held at once to draw batches from, more mixes games from further apart
+    #[serde(default = "default_shuffle_buffer")]
+    pub shuffle_buffer: usize,
+}
+
+fn default_shuffle_buffer() -> usize {
+    100_000
+}
+
+pub struct NnueTrainer {
+    pub net: Nnue,
+    lr: f32,
+}
+
+impl NnueTrainer {
+    /// A trainer with small random initial weights.
+    pub fn new<R: Rng>(hidden: usize, lr: f32, rng: &mut R) -> NnueTrainer {
+        let mut net = Nnue::zeroed(hidden);
+        for weight in net.input_weights.iter_mut() {
+            *weight = rng.gen_range(-0.1..0.1);
+        }
+        for weight in net.output_weights.iter_mut() {
+            *weight = rng.gen_range(-0.5..0.5);
+        }
+        net.input_bias.fill(0.5);
+        NnueTrainer { net, lr }
+    }
+
+    /// Take one gradient step on a batch of (position, target) pairs, returning the mean
+    /// squared error of the batch before the step.
+    ///
+    /// Fails without changing the net if a position is on a board the net doesn't support.
+    pub fn step(&mut self, batch: &[(Game, f64)]) -> Result<f64, UnsupportedBoard> {
+        let features = batch
+            .iter()
+            .map(|(x, _)| active_features(x))
+            .collect::<Result<Vec<_>, _>>()?;
+        let hidden = self.net.hidden;
+        let mut output_grad = vec![0.0; hidden];
+        let mut output_bias_grad = 0.0;
+        let mut input_bias_grad = vec![0.0; hidden];
+        let mut column_grads: HashMap<usize, Vec<f32>> = HashMap::new();
+        let mut loss = 0.0;
+
+        for (features, (_, target)) in features.iter().zip(batch) {
+            let mut acc = self.net.input_bias.clone();
+            for &feature in features {
+                for (value, weight) in acc.iter_mut().zip(self.net.input_column(feature)) {
+                    *value += weight;
+                }
+            }
+            let activated: Vec<f32> = acc.iter().map(|&x| clipped_relu(x)).collect();
+            let mut sum = self.net.output_bias;
+            for (value, weight) in activated.iter().zip(&self.net.output_weights) {
+                sum += value * weight;
+            }
+            let guess = 1.0 / (1.0 + (-sum).exp());
+            let error = guess - *target as f32;
+            loss += (error * error) as f64;
+
+            // d(error^2)/d(sum)
+            let d_sum = 2.0 * error * guess * (1.0 - guess);
+            output_bias_grad += d_sum;
+            for j in 0..hidden {
+                output_grad[j] += d_sum * activated[j];
+                // the clipped relu only passes gradient through its linear section
+                let d_acc = if acc[j] > 0.0 && acc[j] < 1.0 {
+                    d_sum * self.net.output_weights[j]
+                } else {
+                    0.0
+                };
+                input_bias_grad[j] += d_acc;
+                if d_acc != 0.0 {
+                    for &feature in features {
+                        column_grads
+                            .entry(feature)
+                            .or_insert_with(|| vec![0.0; hidden])[j] += d_acc;
+                    }
+                }
+            }
+        }
+
+        let scale = self.lr / batch.len() as f32;
+        for (weight, grad) in self.net.output_weights.iter_mut().zip(&output_grad) {
+            *weight -= scale * grad;
+        }
+        self.net.output_bias -= scale * output_bias_grad;
+        for (weight, grad) in self.net.input_bias.iter_mut().zip(&input_bias_grad) {
+            *weight -= scale * grad;
+        }
+        for (feature, grads) in column_grads {
+            let column = &mut self.net.input_weights[feature * hidden..(feature + 1) * hidden];
+            for (weight, grad) in column.iter_mut().zip(&grads) {
+                *weight -= scale * grad;
+            }
+        }
+        Ok(loss / batch.len() as f64)
+    }
+}
+
+/// Batches drawn from a stream of positions through a shuffle buffer of bounded size.
+///
+/// Once the buffer is full, every incoming item replaces one picked at random, which goes into
+/// the current batch. When the stream runs out the rest of the buffer is shuffled and emptied,
+/// so every item is yielded exactly once, the last batch possibly short.
+pub struct ShuffledBatches<'a, I: Iterator, R> {
+    inner: I,
+    buffer: Vec<I::Item>,
+    capacity: usize,
+    batch_size: usize,
+    rng: &'a mut R,
+}
+
+impl<'a, I: Iterator, R: Rng> ShuffledBatches<'a, I, R> {
+    pub fn new(inner: I, capacity: usize, batch_size: usize, rng: &'a mut R) -> Self {
+        assert!(capacity > 0 && batch_size > 0);
+        ShuffledBatches {
+            inner,
+            buffer: Vec::with_capacity(capacity),
+            capacity,
+            batch_size,
+            rng,
+        }
+    }
+}
+
+impl<I: Iterator, R: Rng> Iterator for ShuffledBatches<'_, I, R> {
+    type Item = Vec<I::Item>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut batch = Vec::with_capacity(self.batch_size);
+        while batch.len() < self.batch_size {
+            match self.inner.next() {
+                Some(item) if self.buffer.len() < self.capacity => self.buffer.push(item),
+                Some(item) => {
+                    let slot = self.rng.gen_range(0..self.buffer.len());
+                    batch.push(std::mem::replace(&mut self.buffer[slot], item));
+                }
+                None => {
+                    self.buffer.shuffle(self.rng);
+                    let rest = self
+                        .buffer
+                        .len()
+                        .saturating_sub(self.batch_size - batch.len());
+                    batch.extend(self.buffer.drain(rest..));
+                    break;
+                }
+            }
+        }
+        (!batch.is_empty()).then_some(batch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    #[test]
+    fn batches_every_item_once() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let batches: Vec<_> = ShuffledBatches::new(0..1000, 64, 30, &mut rng).collect();
+        assert!(batches[..batches.len() - 1].iter().all(|x| x.len() == 30));
+        assert_eq!(batches.last().unwrap().len(), 1000 % 30);
+        let mut items = batches.concat();
+        assert_ne!(items, (0..1000).collect::<Vec<_>>());
+        items.sort_unstable();
+        assert_eq!(items, (0..1000).collect::<Vec<_>>());
+    }
+}
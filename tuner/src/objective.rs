@@ -0,0 +1,106 @@
+//! What training minimises, and fitting the sigmoid scale to it.
+use serde::{Deserialize, Serialize};
+
+use crate::metrics::Metrics;
+
+// keeps the log-loss finite when the prediction saturates
+pub(crate) const EPSILON: f64 = 1e-12;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Objective {
+    /// mean squared error between the prediction and the target
+    #[default]
+    SquaredError,
+    /// cross-entropy between the prediction and the target
+    LogLoss,
+}
+
+impl Objective {
+    /// Loss of a single prediction.
+    pub fn loss(&self, prediction: f64, target: f64) -> f64 {
+        match self {
+            Objective::SquaredError => (target - prediction).powi(2),
+            Objective::LogLoss => {
+                let p = prediction.clamp(EPSILON, 1.0 - EPSILON);
+                -(target * p.ln() + (1.0 - target) * (1.0 - p).ln())
+            }
+        }
+    }
+
+    /// Derivative of the loss with respect to the prediction, negated and scaled to match the
+    /// delta rule, so squared error gives back `target - prediction`.
+    pub fn descent(&self, prediction: f64, target: f64) -> f64 {
+        match self {
+            Objective::SquaredError => target - prediction,
+            Objective::LogLoss => {
+                (target - prediction) / (prediction * (1.0 - prediction)).max(EPSILON)
+            }
+        }
+    }
+
+    /// The value of this objective among a set of measurements.
+    pub fn of(&self, metrics: &Metrics) -> f64 {
+        match self {
+            Objective::SquaredError => metrics.loss,
+            Objective::LogLoss => metrics.log_loss,
+        }
+    }
+}
+
+/// Find the `K` for which `sigmoid(K * score)` best fits the targets, as in Texel tuning.
+///
+/// `scores` pairs each position's weighted sum of features with its target. `K` is searched
+/// for on a log scale between 1/100 and 100.
+pub fn fit_scale(scores: &[(f64, f64)], objective: Objective) -> f64 {
+    let loss = |log_k: f64| {
+        let k = log_k.exp();
+        scores
+            .iter()
+            .map(|&(score, target)| objective.loss(sigmoid(k * score), target))
+            .sum::<f64>()
+    };
+    // golden section search, the loss is unimodal in K for the objectives above
+    let ratio = (5f64.sqrt() - 1.0) / 2.0;
+    let (mut low, mut high) = (-(100f64.ln()), 100f64.ln());
+    let mut a = high - ratio * (high - low);
+    let mut b = low + ratio * (high - low);
+    let (mut loss_a, mut loss_b) = (loss(a), loss(b));
+    while high - low > 1e-6 {
+        if loss_a < loss_b {
+            high = b;
+            (b, loss_b) = (a, loss_a);
+            a = high - ratio * (high - low);
+            loss_a = loss(a);
+        } else {
+            low = a;
+            (a, loss_a) = (b, loss_b);
+            b = low + ratio * (high - low);
+            loss_b = loss(b);
+        }
+    }
+    ((low + high) / 2.0).exp()
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recovers_the_scale_of_calibrated_targets() {
+        // targets that are exactly sigmoid(3 * score)
+        let scores: Vec<_> = (-50..=50)
+            .map(|x| {
+                let score = x as f64 / 25.0;
+                (score, sigmoid(3.0 * score))
+            })
+            .collect();
+        for objective in [Objective::SquaredError, Objective::LogLoss] {
+            assert!((fit_scale(&scores, objective) - 3.0).abs() < 1e-3);
+        }
+    }
+}
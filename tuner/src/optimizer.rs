@@ -0,0 +1,370 @@
+//! Optimizers whose settings come from the config and whose state can be saved in a checkpoint.
+use std::f64::consts::PI;
+
+use nalgebra::SVector;
+use serde::{Deserialize, Serialize};
+use snake_tuner::{
+    activation::ActivationFunction, database::Entry, evaluation::Eval, optimizer::Optimizer,
+};
+
+use crate::objective::Objective;
+
+/// An update rule with a learning rate schedule.
+///
+/// Deserializing from the config only needs the settings, the state fields default to a fresh
+/// optimizer.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScheduledOptimizer {
+    /// the loss the gradients are taken of
+    pub objective: Objective,
+    pub method: Method,
+    /// learning rate before the schedule is applied
+    pub learning_rate: f64,
+    pub schedule: Schedule,
+    /// the learning rate ramps up linearly over this many steps
+    pub warmup: usize,
+    pub regularization: Regularization,
+    /// steps taken so far
+    pub step: usize,
+}
+
+impl Default for ScheduledOptimizer {
+    fn default() -> Self {
+        ScheduledOptimizer {
+            objective: Objective::SquaredError,
+            method: Method::Sgd,
+            learning_rate: 0.01,
+            schedule: Schedule::Constant,
+            warmup: 0,
+            regularization: Regularization::default(),
+            step: 0,
+        }
+    }
+}
+
+impl ScheduledOptimizer {
+    /// The learning rate used for the next step.
+    pub fn current_learning_rate(&self) -> f64 {
+        let warmup = if self.step < self.warmup {
+            (self.step + 1) as f64 / self.warmup as f64
+        } else {
+            1.0
+        };
+        warmup * self.schedule.learning_rate(self.learning_rate, self.step)
+    }
+
+    /// The settings of the optimizer without its accumulated state, for recording alongside
+    /// results.
+    pub fn settings(&self) -> ScheduledOptimizer {
+        ScheduledOptimizer {
+            method: self.method.settings(),
+            ..self.clone()
+        }
+    }
+}
+
+// the penalties need the current weights, which can only be read back from evaluations where
+// every weight multiplies one input
+impl<A: ActivationFunction, const W: usize, E: Eval<W, W, A>> Optimizer<A, W, W, E>
+    for ScheduledOptimizer
+{
+    fn step<T: Entry<W>>(&mut self, eval: &mut E, entries: Vec<T>) {
+        let weights = SVector::from_fn(|i, _| {
+            let mut unit = SVector::zeros();
+            unit[i] = 1.0;
+            eval.forward(unit)
+        });
+        let gradient =
+            gradient(eval, &entries, self.objective) - self.regularization.gradient(&weights);
+        let update = self.method.update(gradient, self.current_learning_rate());
+        eval.nudge_weights(update);
+        self.step += 1;
+    }
+
+    fn reset(&mut self) {
+        self.method = self.method.settings();
+        self.step = 0;
+    }
+}
+
+/// Penalties on the size of the weights.
+///
+/// The last weight is the bias in every feature set here, and isn't penalised.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Regularization {
+    /// strength of the penalty on the sum of absolute weights
+    pub l1: f64,
+    /// strength of the penalty on half the sum of squared weights
+    pub l2: f64,
+}
+
+impl Regularization {
+    /// Gradient of the penalty, to be subtracted from the descent direction.
+    pub fn gradient<const W: usize>(&self, weights: &SVector<f64, W>) -> SVector<f64, W> {
+        SVector::from_fn(|i, _| {
+            let sign = if weights[i] == 0.0 {
+                0.0
+            } else {
+                weights[i].signum()
+            };
+            if i + 1 == W {
+                0.0
+            } else {
+                self.l1 * sign + self.l2 * weights[i]
+            }
+        })
+    }
+}
+
+/// How a gradient is turned into a weight update.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Method {
+    /// plain stochastic gradient descent
+    Sgd,
+    /// gradient descent with a running sum of past gradients
+    Momentum {
+        #[serde(default = "default_momentum")]
+        momentum: f64,
+        #[serde(default)]
+        velocity: Vec<f64>,
+    },
+    Adam {
+        #[serde(default = "default_beta1")]
+        beta1: f64,
+        #[serde(default = "default_beta2")]
+        beta2: f64,
+        #[serde(default = "default_epsilon")]
+        epsilon: f64,
+        /// first moment estimate
+        #[serde(default)]
+        m: Vec<f64>,
+        /// second moment estimate
+        #[serde(default)]
+        v: Vec<f64>,
+        /// updates made, for bias correction
+        #[serde(default)]
+        t: u64,
+    },
+    /// per weight learning rates that shrink with the sum of squared gradients
+    AdaGrad {
+        #[serde(default = "default_epsilon")]
+        epsilon: f64,
+        #[serde(default)]
+        sum_of_squares: Vec<f64>,
+    },
+}
+
+fn default_momentum() -> f64 {
+    0.9
+}
+
+fn default_beta1() -> f64 {
+    0.9
+}
+
+fn default_beta2() -> f64 {
+    0.999
+}
+
+fn default_epsilon() -> f64 {
+    1e-8
+}
+
+impl Method {
+    /// The method with the same hyperparameters and fresh state.
+    pub fn settings(&self) -> Method {
+        match *self {
+            Method::Sgd => Method::Sgd,
+            Method::Momentum { momentum, .. } => Method::Momentum {
+                momentum,
+                velocity: vec![],
+            },
+            Method::Adam {
+                beta1,
+                beta2,
+                epsilon,
+                ..
+            } => Method::Adam {
+                beta1,
+                beta2,
+                epsilon,
+                m: vec![],
+                v: vec![],
+                t: 0,
+            },
+            Method::AdaGrad { epsilon, .. } => Method::AdaGrad {
+                epsilon,
+                sum_of_squares: vec![],
+            },
+        }
+    }
+
+    // the amount to nudge the weights by for a descent direction `gradient`
+    fn update<const W: usize>(
+        &mut self,
+        gradient: SVector<f64, W>,
+        learning_rate: f64,
+    ) -> SVector<f64, W> {
+        match self {
+            Method::Sgd => gradient * learning_rate,
+            Method::Momentum { momentum, velocity } => {
+                velocity.resize(W, 0.0);
+                SVector::from_fn(|i, _| {
+                    velocity[i] = *momentum * velocity[i] + gradient[i];
+                    velocity[i] * learning_rate
+                })
+            }
+            Method::Adam {
+                beta1,
+                beta2,
+                epsilon,
+                m,
+                v,
+                t,
+            } => {
+                m.resize(W, 0.0);
+                v.resize(W, 0.0);
+                *t += 1;
+                let (correction1, correction2) =
+                    (1.0 - beta1.powi(*t as i32), 1.0 - beta2.powi(*t as i32));
+                SVector::from_fn(|i, _| {
+                    m[i] = *beta1 * m[i] + (1.0 - *beta1) * gradient[i];
+                    v[i] = *beta2 * v[i] + (1.0 - *beta2) * gradient[i].powi(2);
+                    learning_rate * (m[i] / correction1) / ((v[i] / correction2).sqrt() + *epsilon)
+                })
+            }
+            Method::AdaGrad {
+                epsilon,
+                sum_of_squares,
+            } => {
+                sum_of_squares.resize(W, 0.0);
+                SVector::from_fn(|i, _| {
+                    sum_of_squares[i] += gradient[i].powi(2);
+                    learning_rate * gradient[i] / (sum_of_squares[i].sqrt() + *epsilon)
+                })
+            }
+        }
+    }
+}
+
+/// How the learning rate changes over the course of training.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Schedule {
+    Constant,
+    /// multiply the learning rate by `factor` every `every` steps
+    StepDecay {
+        every: usize,
+        factor: f64,
+    },
+    /// anneal from the learning rate down to `min_learning_rate` over `steps` steps
+    Cosine {
+        steps: usize,
+        #[serde(default)]
+        min_learning_rate: f64,
+    },
+}
+
+impl Schedule {
+    pub fn learning_rate(&self, base: f64, step: usize) -> f64 {
+        match *self {
+            Schedule::Constant => base,
+            Schedule::StepDecay { every, factor } => {
+                base * factor.powi((step / every.max(1)) as i32)
+            }
+            Schedule::Cosine {
+                steps,
+                min_learning_rate,
+            } => {
+                let progress = step.min(steps) as f64 / steps.max(1) as f64;
+                min_learning_rate + (base - min_learning_rate) * 0.5 * (1.0 + (PI * progress).cos())
+            }
+        }
+    }
+}
+
+/// Mean of the delta rule updates over a batch, pointing in the direction that reduces the
+/// objective.
+pub fn gradient<A, const W: usize, const C: usize, E, T>(
+    eval: &E,
+    entries: &[T],
+    objective: Objective,
+) -> SVector<f64, W>
+where
+    A: ActivationFunction,
+    E: Eval<C, W, A>,
+    T: Entry<C>,
+{
+    let mut accumulator = SVector::zeros();
+    for entry in entries {
+        let activation = eval.forward(entry.get_inputs());
+        let prediction = eval.activation_fn().evaluate(activation);
+        let error = objective.descent(prediction, entry.get_expected_output());
+        accumulator += eval.derivative_vector(entry.get_inputs())
+            * eval.activation_fn().derivative(activation)
+            * error;
+    }
+    accumulator / entries.len().max(1) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schedules_the_learning_rate() {
+        let decay = Schedule::StepDecay {
+            every: 10,
+            factor: 0.5,
+        };
+        assert_eq!(decay.learning_rate(1.0, 9), 1.0);
+        assert_eq!(decay.learning_rate(1.0, 25), 0.25);
+        let cosine = Schedule::Cosine {
+            steps: 100,
+            min_learning_rate: 0.1,
+        };
+        assert_eq!(cosine.learning_rate(1.0, 0), 1.0);
+        assert!((cosine.learning_rate(1.0, 50) - 0.55).abs() < 1e-12);
+        assert_eq!(cosine.learning_rate(1.0, 200), 0.1);
+        let warmup = ScheduledOptimizer {
+            warmup: 4,
+            step: 1,
+            ..Default::default()
+        };
+        assert_eq!(warmup.current_learning_rate(), 0.005);
+    }
+
+    #[test]
+    fn penalises_everything_but_the_bias() {
+        let regularization = Regularization { l1: 0.5, l2: 2.0 };
+        let weights = SVector::from([1.0, -2.0, 0.0, 3.0]);
+        assert_eq!(
+            regularization.gradient(&weights),
+            SVector::from([2.5, -4.5, 0.0, 0.0])
+        );
+    }
+
+    #[test]
+    fn reads_settings_from_toml() {
+        let optimizer: ScheduledOptimizer = toml::from_str(
+            "learning_rate = 0.1\nwarmup = 100\n\n[method]\nkind = \"adam\"\nbeta2 = 0.99\n\n[schedule]\nkind = \"cosine\"\nsteps = 1000\n",
+        )
+        .unwrap();
+        assert_eq!(
+            optimizer.method,
+            Method::Adam {
+                beta1: 0.9,
+                beta2: 0.99,
+                epsilon: 1e-8,
+                m: vec![],
+                v: vec![],
+                t: 0
+            }
+        );
+        assert_eq!(optimizer.learning_rate, 0.1);
+        assert_eq!(optimizer.step, 0);
+    }
+}
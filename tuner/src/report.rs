@@ -0,0 +1,274 @@
+//! A closer look at how a set of weights performs than a single loss.
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use nalgebra::SVector;
+use snake_tuner::{
+    activation::{functions::Sigmoid, ActivationFunction},
+    evaluation::{evaluations::Linear, Eval},
+};
+
+use crate::{
+    dataset::{ComputedEntry, FEATURES},
+    metrics::Metrics,
+};
+
+/// Turn ranges the accuracy is broken down by, each one starting on the given turn and running
+/// up to the next.
+pub const PHASES: [u32; 5] = [0, 25, 50, 100, 200];
+
+pub struct Report {
+    pub calibration: Vec<Bucket>,
+    pub ablation: Vec<Ablation>,
+    /// only covers the positions whose turn is known
+    pub phases: Vec<Phase>,
+}
+
+/// Positions whose predictions fall in one decile.
+pub struct Bucket {
+    pub lowest: f64,
+    pub highest: f64,
+    pub count: usize,
+    /// mean predicted win probability
+    pub predicted: f64,
+    /// fraction of the positions that were won, counting draws as half
+    pub actual: f64,
+}
+
+/// How much worse the weights do when one feature is replaced by its mean.
+pub struct Ablation {
+    pub feature: String,
+    pub loss_delta: f64,
+    pub log_loss_delta: f64,
+    pub accuracy_delta: f64,
+}
+
+/// Accuracy on the positions from a range of turns.
+pub struct Phase {
+    pub first_turn: u32,
+    /// `None` for the last phase, which runs to the end of the game
+    pub last_turn: Option<u32>,
+    pub count: usize,
+    pub metrics: Metrics,
+}
+
+impl Report {
+    pub fn new(
+        eval: &Linear<FEATURES, Sigmoid>,
+        features: &[&str],
+        entries: &[ComputedEntry],
+    ) -> Report {
+        Report {
+            calibration: calibration(eval, entries),
+            ablation: ablation(eval, features, entries),
+            phases: phases(eval, entries),
+        }
+    }
+
+    /// Write `calibration.csv`, `ablation.csv` and `phases.csv` into `directory`.
+    pub fn write_csv<P: AsRef<Path>>(&self, directory: P) -> io::Result<()> {
+        let directory = directory.as_ref();
+        fs::create_dir_all(directory)?;
+        let mut file = BufWriter::new(File::create(directory.join("calibration.csv"))?);
+        writeln!(file, "lowest,highest,count,predicted,actual")?;
+        for x in &self.calibration {
+            writeln!(
+                file,
+                "{},{},{},{},{}",
+                x.lowest, x.highest, x.count, x.predicted, x.actual
+            )?;
+        }
+        file.flush()?;
+        let mut file = BufWriter::new(File::create(directory.join("ablation.csv"))?);
+        writeln!(file, "feature,loss_delta,log_loss_delta,accuracy_delta")?;
+        for x in &self.ablation {
+            writeln!(
+                file,
+                "{},{},{},{}",
+                x.feature, x.loss_delta, x.log_loss_delta, x.accuracy_delta
+            )?;
+        }
+        file.flush()?;
+        let mut file = BufWriter::new(File::create(directory.join("phases.csv"))?);
+        writeln!(file, "first_turn,last_turn,count,loss,log_loss,accuracy")?;
+        for x in &self.phases {
+            let last_turn = x.last_turn.map(|x| x.to_string()).unwrap_or_default();
+            writeln!(
+                file,
+                "{},{},{},{},{},{}",
+                x.first_turn,
+                last_turn,
+                x.count,
+                x.metrics.loss,
+                x.metrics.log_loss,
+                x.metrics.accuracy
+            )?;
+        }
+        file.flush()
+    }
+}
+
+fn predict(eval: &Linear<FEATURES, Sigmoid>, input: SVector<f64, FEATURES>) -> f64 {
+    eval.activation_fn().evaluate(eval.forward(input))
+}
+
+fn calibration(eval: &Linear<FEATURES, Sigmoid>, entries: &[ComputedEntry]) -> Vec<Bucket> {
+    let mut predictions: Vec<_> = entries
+        .iter()
+        .map(|x| (predict(eval, x.input), x.result))
+        .collect();
+    predictions.sort_by(|a, b| a.0.total_cmp(&b.0));
+    (0..10)
+        .map(|decile| {
+            &predictions[decile * predictions.len() / 10..(decile + 1) * predictions.len() / 10]
+        })
+        .filter(|bucket| !bucket.is_empty())
+        .map(|bucket| {
+            let count = bucket.len();
+            Bucket {
+                lowest: bucket[0].0,
+                highest: bucket[count - 1].0,
+                count,
+                predicted: bucket.iter().map(|x| x.0).sum::<f64>() / count as f64,
+                actual: bucket.iter().map(|x| x.1).sum::<f64>() / count as f64,
+            }
+        })
+        .collect()
+}
+
+fn ablation(
+    eval: &Linear<FEATURES, Sigmoid>,
+    features: &[&str],
+    entries: &[ComputedEntry],
+) -> Vec<Ablation> {
+    let baseline = Metrics::measure(eval, entries);
+    let count = entries.len().max(1) as f64;
+    (0..FEATURES)
+        .map(|feature| {
+            let mean = entries.iter().map(|x| x.input[feature]).sum::<f64>() / count;
+            let ablated: Vec<_> = entries
+                .iter()
+                .map(|x| {
+                    let mut x = *x;
+                    x.input[feature] = mean;
+                    x
+                })
+                .collect();
+            let metrics = Metrics::measure(eval, &ablated);
+            Ablation {
+                feature: features[feature].to_string(),
+                loss_delta: metrics.loss - baseline.loss,
+                log_loss_delta: metrics.log_loss - baseline.log_loss,
+                accuracy_delta: metrics.accuracy - baseline.accuracy,
+            }
+        })
+        .collect()
+}
+
+fn phases(eval: &Linear<FEATURES, Sigmoid>, entries: &[ComputedEntry]) -> Vec<Phase> {
+    (0..PHASES.len())
+        .map(|i| {
+            let first_turn = PHASES[i];
+            let last_turn = PHASES.get(i + 1).map(|x| x - 1);
+            let phase: Vec<_> = entries
+                .iter()
+                .filter(|x| x.turn != ComputedEntry::UNKNOWN_TURN)
+                .filter(|x| x.turn >= first_turn && last_turn.is_none_or(|last| x.turn <= last))
+                .copied()
+                .collect();
+            Phase {
+                first_turn,
+                last_turn,
+                count: phase.len(),
+                metrics: Metrics::measure(eval, &phase),
+            }
+        })
+        .collect()
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "calibration")?;
+        writeln!(
+            f,
+            "{:>17} {:>8} {:>10} {:>10}",
+            "predictions", "count", "predicted", "actual"
+        )?;
+        for x in &self.calibration {
+            writeln!(
+                f,
+                "{:>7.4} - {:>7.4} {:>8} {:>10.4} {:>10.4}",
+                x.lowest, x.highest, x.count, x.predicted, x.actual
+            )?;
+        }
+        writeln!(
+            f,
+            "\nablation, change when the feature is replaced by its mean"
+        )?;
+        writeln!(
+            f,
+            "{:>28} {:>12} {:>12} {:>10}",
+            "feature", "loss", "log-loss", "accuracy"
+        )?;
+        for x in &self.ablation {
+            writeln!(
+                f,
+                "{:>28} {:>+12.6} {:>+12.6} {:>+9.2}%",
+                x.feature,
+                x.loss_delta,
+                x.log_loss_delta,
+                x.accuracy_delta * 100.0
+            )?;
+        }
+        writeln!(f, "\nby turn")?;
+        for x in &self.phases {
+            let last_turn = x.last_turn.map(|x| x.to_string()).unwrap_or_default();
+            writeln!(
+                f,
+                "{:>4} - {:<4} {:>8} positions: {}",
+                x.first_turn, last_turn, x.count, x.metrics
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_and_phases_cover_every_entry() {
+        let entries: Vec<_> = (0..95)
+            .map(|x| ComputedEntry {
+                input: SVector::from([x as f64 / 95.0 - 0.5, 0.0, 0.0, 0.0, 0.0, 1.0]),
+                result: (x >= 50) as u8 as f64,
+                turns_until_end: 0,
+                game: x / 5,
+                // the last five don't know their turn
+                turn: if x < 90 {
+                    x * 3
+                } else {
+                    ComputedEntry::UNKNOWN_TURN
+                },
+                output: (x >= 50) as u8 as f64,
+            })
+            .collect();
+        let eval = Linear::from_weights(SVector::from([10.0, 0.0, 0.0, 0.0, 0.0, 0.0]), Sigmoid);
+        let report = Report::new(&eval, &["a", "b", "c", "d", "e", "bias"], &entries);
+        assert_eq!(report.calibration.len(), 10);
+        assert_eq!(
+            report.calibration.iter().map(|x| x.count).sum::<usize>(),
+            95
+        );
+        assert!(report.calibration[0].actual < report.calibration[9].actual);
+        assert_eq!(report.phases.iter().map(|x| x.count).sum::<usize>(), 90);
+        // only the first feature carries any weight
+        assert!(report.ablation[0].loss_delta > 0.0);
+        assert_eq!(report.ablation[1].loss_delta, 0.0);
+    }
+}
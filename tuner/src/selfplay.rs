@@ -0,0 +1,199 @@
+//! Duels between two evaluations, played out locally under standard rules.
+use board::{
+    rules::{Move, MAX_HEALTH},
+    useful_board::{Board, Game, Snake},
+    Coordinate,
+};
+
+use eval::{
+    area_eval::AreaEval,
+    cache::EvalCache,
+    search::{Line, Search, SearchStatistics},
+    terminal::Terminal,
+};
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+/// Where snakes can start on an 11x11 board.
+const SPAWNS: [(i8, i8); 8] = [
+    (1, 1),
+    (1, 5),
+    (1, 9),
+    (5, 1),
+    (5, 9),
+    (9, 1),
+    (9, 5),
+    (9, 9),
+];
+const SIZE: u32 = 11;
+/// Food is topped up to this much every turn.
+const MINIMUM_FOOD: usize = 1;
+/// Chance of another food appearing on a turn that already has enough.
+const FOOD_CHANCE: f64 = 0.15;
+/// Scores each player's evaluation cache holds over a game.
+const CACHE_SIZE: usize = 1 << 14;
+
+/// `[self_play]` section of the tuner config.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SelfPlayConfig {
+    /// turns each move is searched ahead
+    pub depth: u32,
+    /// games still going after this many turns are drawn
+    pub max_turns: u32,
+}
+
+impl Default for SelfPlayConfig {
+    fn default() -> Self {
+        SelfPlayConfig {
+            depth: 1,
+            max_turns: 300,
+        }
+    }
+}
+
+/// How a game went.
+pub struct Played {
+    /// 1 for a win of `players[0]`, 0 for a loss and 0.5 for a draw
+    pub result: f64,
+    pub search: SearchStatistics,
+}
+
+/// A standard start for two snakes, `"a"` and `"b"`: both stacked on a random spawn with full
+/// health, a food next to each and one in the center.
+pub fn start<R: Rng>(rng: &mut R) -> Game {
+    let mut spawns = SPAWNS;
+    spawns.shuffle(rng);
+    let center = Coordinate::new(SIZE as i8 / 2, SIZE as i8 / 2);
+    let distance = |c: Coordinate| (c.x - center.x).abs() + (c.y - center.y).abs();
+    let mut board = Board {
+        width: SIZE,
+        height: SIZE,
+        snakes: ["a", "b"]
+            .iter()
+            .zip(spawns)
+            .map(|(id, (x, y))| Snake {
+                id: id.to_string(),
+                body: vec![Coordinate::new(x, y); 3],
+                health: MAX_HEALTH,
+            })
+            .collect(),
+        food: vec![],
+        hazards: vec![],
+    };
+    for i in 0..board.snakes.len() {
+        let head = board.snakes[i].body[0];
+        // a diagonal neighbour, no further from the center than the snake
+        let free = board.unoccupied();
+        let candidates: Vec<_> = [(-1, -1), (-1, 1), (1, -1), (1, 1)]
+            .iter()
+            .map(|&(x, y)| head + Coordinate::new(x, y))
+            .filter(|&x| x != center && distance(x) <= distance(head) && free.contains(&x))
+            .collect();
+        if let Some(&food) = candidates.choose(rng) {
+            board.food.push(food);
+        }
+    }
+    board.food.push(center);
+    Game {
+        board,
+        you_id: "a".to_string(),
+        turn: 0,
+    }
+}
+
+fn spawn_food<R: Rng>(board: &mut Board, rng: &mut R) {
+    if board.food.len() < MINIMUM_FOOD || rng.gen_bool(FOOD_CHANCE) {
+        if let Some(&food) = board.unoccupied().choose(rng) {
+            board.food.push(food);
+        }
+    }
+}
+
+/// Play a game from `start`, with `players[0]` as snake `"a"` and `players[1]` as `"b"`.
+///
+/// Running out of turns counts as a draw. Each player searches with its own evaluation cache.
+pub fn play<R: Rng>(
+    start: Game,
+    players: [&AreaEval; 2],
+    config: &SelfPlayConfig,
+    rng: &mut R,
+) -> Played {
+    play_observed(start, players, config, rng, |_, _| {})
+}
+
+/// [`play`], calling `observe` with every position searched along the way, from the perspective
+/// of the snake to move, and the line that was found.
+pub fn play_observed<R: Rng, F: FnMut(&Game, &Line)>(
+    start: Game,
+    players: [&AreaEval; 2],
+    config: &SelfPlayConfig,
+    rng: &mut R,
+    mut observe: F,
+) -> Played {
+    let mut game = start;
+    let mut searches = players.map(|x| Search::new(EvalCache::new(x.clone(), CACHE_SIZE)));
+    while game.turn < config.max_turns && Terminal::detect(&game).is_none() {
+        let moves: Vec<Move> = game
+            .board
+            .snakes
+            .iter()
+            .map(|snake| {
+                let mut position = game.clone();
+                position.you_id = snake.id.clone();
+                let player = (snake.id != "a") as usize;
+                let line = searches[player].best_line(&position, config.depth);
+                observe(&position, &line);
+                line.mov
+            })
+            .collect();
+        game.advance(&moves);
+        spawn_food(&mut game.board, rng);
+    }
+    game.you_id = "a".to_string();
+    let result = match Terminal::detect(&game) {
+        Some(Terminal::Won) => 1.0,
+        Some(Terminal::Lost) => 0.0,
+        Some(Terminal::Draw) | None => 0.5,
+    };
+    Played {
+        result,
+        search: searches.iter().map(Search::statistics).sum(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    #[test]
+    fn plays_a_game_to_the_end() {
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let start = start(&mut rng);
+        assert_eq!(start.board.snakes.len(), 2);
+        assert_eq!(start.board.food.len(), 3);
+        assert_ne!(start.board.snakes[0].body[0], start.board.snakes[1].body[0]);
+        // reaching food first is better, against an opponent that doesn't care
+        let good = AreaEval::new([0.0, 0.0, 0.0, 5.0, 0.0, 0.0]);
+        let careless = AreaEval::new([0.0; 6]);
+        let config = SelfPlayConfig {
+            max_turns: 100,
+            ..Default::default()
+        };
+        let mut score = 0.0;
+        for _ in 0..4 {
+            let start = super::start(&mut rng);
+            let first = play(start.clone(), [&good, &careless], &config, &mut rng.clone());
+            score += first.result;
+            score += 1.0 - play(start, [&careless, &good], &config, &mut rng.clone()).result;
+            // at depth 1 every node is a leaf, and all but the finished games are looked up in
+            // the cache
+            let cache = first.search.cache;
+            assert!(cache.misses > 0 && cache.hits + cache.misses <= first.search.nodes);
+        }
+        assert!(score > 4.0, "{score}");
+    }
+}
@@ -0,0 +1,101 @@
+//! Splitting a dataset into training, validation and test sets.
+use serde::Deserialize;
+
+use crate::dataset::{ComputedEntry, DB};
+
+/// Fractions of the games that go into each set, the test set gets whatever is left.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct SplitConfig {
+    pub train: f64,
+    pub validation: f64,
+}
+
+impl Default for SplitConfig {
+    fn default() -> Self {
+        SplitConfig {
+            train: 0.8,
+            validation: 0.1,
+        }
+    }
+}
+
+pub struct Split {
+    pub train: DB,
+    pub validation: DB,
+    pub test: DB,
+}
+
+impl Split {
+    /// Split the entries by game, so positions from one game never end up in two sets.
+    ///
+    /// Games are assigned by a hash of their index and `seed`, which keeps the split the same
+    /// between runs on the same cache with the same seed.
+    pub fn by_game(entries: &[ComputedEntry], config: SplitConfig, seed: u64) -> Split {
+        let mut split = Split {
+            train: DB { entries: vec![] },
+            validation: DB { entries: vec![] },
+            test: DB { entries: vec![] },
+        };
+        for entry in entries {
+            let x = unit(entry.game, seed);
+            let set = if x < config.train {
+                &mut split.train
+            } else if x < config.train + config.validation {
+                &mut split.validation
+            } else {
+                &mut split.test
+            };
+            set.entries.push(*entry);
+        }
+        split
+    }
+}
+
+// spread game indices evenly over [0, 1), differently for every seed
+fn unit(game: u32, seed: u64) -> f64 {
+    // splitmix64 finaliser
+    let mut z = (game as u64 ^ seed.rotate_left(32)).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn keeps_games_in_one_set() {
+        let entries: Vec<_> = (0..1000)
+            .map(|x| ComputedEntry {
+                input: Default::default(),
+                result: 1.0,
+                turns_until_end: x % 10,
+                game: x / 10,
+                turn: x % 10,
+                output: 0.0,
+            })
+            .collect();
+        let split = Split::by_game(&entries, SplitConfig::default(), 7);
+        let games = |db: &DB| db.entries.iter().map(|x| x.game).collect::<HashSet<_>>();
+        let (train, validation, test) = (
+            games(&split.train),
+            games(&split.validation),
+            games(&split.test),
+        );
+        assert!(train.is_disjoint(&validation));
+        assert!(train.is_disjoint(&test));
+        assert!(validation.is_disjoint(&test));
+        assert_eq!(train.len() + validation.len() + test.len(), 100);
+        assert!(train.len() > validation.len() && train.len() > test.len());
+        // the same seed always gives the same split, another seed a different one
+        let again = Split::by_game(&entries, SplitConfig::default(), 7);
+        assert_eq!(split.train.entries, again.train.entries);
+        let other = Split::by_game(&entries, SplitConfig::default(), 8);
+        assert_ne!(split.train.entries, other.train.entries);
+    }
+}
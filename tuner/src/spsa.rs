@@ -0,0 +1,218 @@
+//! Tuning the area evaluation by self-play with simultaneous perturbation stochastic
+//! approximation.
+//!
+//! Every iteration nudges all weights at once by `±c_k` in a random direction, plays the two
+//! perturbed evaluations against each other, and steps the weights towards whichever side won
+//! more. The match result stands in for the difference in strength, so no dataset is needed.
+use std::{fs, io, path::Path};
+
+use eval::{area_eval::AreaEval, search::SearchStatistics};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dataset::FEATURES,
+    selfplay::{self, SelfPlayConfig},
+};
+
+/// `[spsa]` section of the tuner config.
+///
+/// The gains follow Spall's recommendations: `a_k = a / (k + 1 + stability) ^ alpha` and
+/// `c_k = c / (k + 1) ^ gamma` on iteration `k`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpsaConfig {
+    pub iterations: usize,
+    /// games per iteration come in pairs, which play the same start with the sides swapped
+    pub pairs: usize,
+    /// step size
+    pub a: f64,
+    /// size of the perturbation
+    pub c: f64,
+    /// keeps the first steps from being much larger than the rest
+    pub stability: f64,
+    pub alpha: f64,
+    pub gamma: f64,
+    /// where the tuned weights are written, after every iteration
+    pub output: String,
+}
+
+impl Default for SpsaConfig {
+    fn default() -> Self {
+        SpsaConfig {
+            iterations: 200,
+            pairs: 8,
+            a: 1.0,
+            c: 0.5,
+            stability: 20.0,
+            alpha: 0.602,
+            gamma: 0.101,
+            output: "spsa.toml".to_string(),
+        }
+    }
+}
+
+pub struct Spsa {
+    pub config: SpsaConfig,
+    pub self_play: SelfPlayConfig,
+    /// in the order of [`AreaEval::FEATURES`]
+    pub weights: [f64; FEATURES],
+    /// completed iterations
+    pub iteration: usize,
+    pub seed: u64,
+    rng: ChaCha8Rng,
+}
+
+/// What happened on one iteration.
+pub struct Iteration {
+    /// score of the positive perturbation against the negative one, from 0 to 1
+    pub score: f64,
+    pub step_size: f64,
+    pub perturbation: f64,
+    pub search: SearchStatistics,
+}
+
+impl Spsa {
+    /// Start tuning from `weights`. The games and perturbations are all drawn from `seed`.
+    pub fn new(
+        config: SpsaConfig,
+        self_play: SelfPlayConfig,
+        weights: [f64; FEATURES],
+        seed: u64,
+    ) -> Spsa {
+        Spsa {
+            config,
+            self_play,
+            weights,
+            iteration: 0,
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Play one iteration's games, in parallel, and update the weights.
+    pub fn step(&mut self) -> Iteration {
+        let k = self.iteration as f64;
+        let step_size = self.config.a / (k + 1.0 + self.config.stability).powf(self.config.alpha);
+        let perturbation = self.config.c / (k + 1.0).powf(self.config.gamma);
+        // the bias only shifts every score by the same amount, which never changes a move, so
+        // it's left alone
+        let direction: [f64; FEATURES] = std::array::from_fn(|i| match i {
+            i if i == FEATURES - 1 => 0.0,
+            _ if self.rng.gen() => 1.0,
+            _ => -1.0,
+        });
+        let plus = AreaEval::new(std::array::from_fn(|i| {
+            self.weights[i] + perturbation * direction[i]
+        }));
+        let minus = AreaEval::new(std::array::from_fn(|i| {
+            self.weights[i] - perturbation * direction[i]
+        }));
+        let games: u64 = self.rng.gen();
+        let results: Vec<(f64, SearchStatistics)> = (0..self.config.pairs)
+            .into_par_iter()
+            .map(|pair| {
+                let mut rng = ChaCha8Rng::seed_from_u64(games);
+                rng.set_stream(pair as u64);
+                let start = selfplay::start(&mut rng);
+                // the second game gets the same start and food draws with the sides swapped
+                let first = selfplay::play(
+                    start.clone(),
+                    [&plus, &minus],
+                    &self.self_play,
+                    &mut rng.clone(),
+                );
+                let second = selfplay::play(start, [&minus, &plus], &self.self_play, &mut rng);
+                (
+                    first.result + 1.0 - second.result,
+                    first.search + second.search,
+                )
+            })
+            .collect();
+        let score =
+            results.iter().map(|x| x.0).sum::<f64>() / (2 * self.config.pairs).max(1) as f64;
+        // the score of plus minus the score of minus is 2 * score - 1
+        let gradient = (2.0 * score - 1.0) / (2.0 * perturbation);
+        for (weight, direction) in self.weights.iter_mut().zip(direction) {
+            // dividing by a direction of ±1 is the same as multiplying by it
+            *weight += step_size * gradient * direction;
+        }
+        self.iteration += 1;
+        Iteration {
+            score,
+            step_size,
+            perturbation,
+            search: results.into_iter().map(|x| x.1).sum(),
+        }
+    }
+}
+
+/// Weights tuned by self-play, loadable with `AreaEval::load`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpsaFile {
+    pub weights: [f64; FEATURES],
+    pub metadata: SpsaMetadata,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpsaMetadata {
+    pub features: Vec<String>,
+    pub iterations: usize,
+    pub seed: u64,
+    pub spsa: SpsaConfig,
+    pub self_play: SelfPlayConfig,
+}
+
+impl SpsaFile {
+    pub fn new(spsa: &Spsa) -> SpsaFile {
+        SpsaFile {
+            weights: spsa.weights,
+            metadata: SpsaMetadata {
+                features: AreaEval::FEATURES.map(String::from).to_vec(),
+                iterations: spsa.iteration,
+                seed: spsa.seed,
+                spsa: spsa.config.clone(),
+                self_play: spsa.self_play,
+            },
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let text =
+            toml::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_deterministically() {
+        let config = SpsaConfig {
+            iterations: 1,
+            pairs: 2,
+            ..Default::default()
+        };
+        // short games, only the bookkeeping is checked here
+        let games = SelfPlayConfig {
+            max_turns: 50,
+            ..Default::default()
+        };
+        let mut spsa = Spsa::new(config.clone(), games, [0.0; FEATURES], 9);
+        let iteration = spsa.step();
+        assert!((0.0..=1.0).contains(&iteration.score));
+        assert_eq!(spsa.weights[FEATURES - 1], 0.0);
+        let mut again = Spsa::new(config, games, [0.0; FEATURES], 9);
+        again.step();
+        assert_eq!(spsa.weights, again.weights);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("weights.toml");
+        SpsaFile::new(&spsa).save(&path).unwrap();
+        assert_eq!(AreaEval::load(&path).unwrap().weights(), spsa.weights);
+    }
+}
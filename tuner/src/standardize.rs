@@ -0,0 +1,136 @@
+//! Rescaling features to zero mean and unit variance.
+use nalgebra::SVector;
+use serde::{Deserialize, Serialize};
+
+use crate::dataset::{ComputedEntry, FEATURES};
+
+/// Per-feature statistics of a training set, and the transform they define.
+///
+/// Constant features, like the bias, are left alone. The first constant, nonzero feature acts
+/// as the intercept that absorbs the shift from centering the others.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Standardization {
+    pub mean: [f64; FEATURES],
+    pub std: [f64; FEATURES],
+}
+
+impl Standardization {
+    pub fn fit(entries: &[ComputedEntry]) -> Standardization {
+        let count = entries.len().max(1) as f64;
+        let mut mean = [0.0; FEATURES];
+        let mut std = [0.0; FEATURES];
+        for entry in entries {
+            for (i, x) in entry.input.iter().enumerate() {
+                mean[i] += x / count;
+            }
+        }
+        for entry in entries {
+            for (i, x) in entry.input.iter().enumerate() {
+                std[i] += (x - mean[i]).powi(2) / count;
+            }
+        }
+        Standardization {
+            mean,
+            std: std.map(f64::sqrt),
+        }
+    }
+
+    fn is_constant(&self, feature: usize) -> bool {
+        self.std[feature] < 1e-12
+    }
+
+    // the feature that absorbs the offsets, and its value
+    fn intercept(&self) -> Option<(usize, f64)> {
+        (0..FEATURES)
+            .find(|&i| self.is_constant(i) && self.mean[i].abs() > 1e-12)
+            .map(|i| (i, self.mean[i]))
+    }
+
+    // whether a feature is centered, as well as scaled
+    fn centers(&self, feature: usize) -> bool {
+        !self.is_constant(feature) && self.intercept().is_some()
+    }
+
+    /// Transform raw features into standardised ones.
+    pub fn apply(&self, input: &SVector<f64, FEATURES>) -> SVector<f64, FEATURES> {
+        SVector::from_fn(|i, _| {
+            if self.is_constant(i) {
+                input[i]
+            } else if self.centers(i) {
+                (input[i] - self.mean[i]) / self.std[i]
+            } else {
+                input[i] / self.std[i]
+            }
+        })
+    }
+
+    /// Convert weights for standardised features into weights for raw features, so that both
+    /// give the same score.
+    pub fn fold(&self, weights: [f64; FEATURES]) -> [f64; FEATURES] {
+        let mut raw = weights;
+        let mut offset = 0.0;
+        for i in (0..FEATURES).filter(|&i| !self.is_constant(i)) {
+            raw[i] = weights[i] / self.std[i];
+            if self.centers(i) {
+                offset += raw[i] * self.mean[i];
+            }
+        }
+        if let Some((intercept, value)) = self.intercept() {
+            raw[intercept] -= offset / value;
+        }
+        raw
+    }
+
+    /// The inverse of [`Standardization::fold`], for starting training from raw weights.
+    pub fn unfold(&self, raw: [f64; FEATURES]) -> [f64; FEATURES] {
+        let mut weights = raw;
+        let mut offset = 0.0;
+        for i in (0..FEATURES).filter(|&i| !self.is_constant(i)) {
+            weights[i] = raw[i] * self.std[i];
+            if self.centers(i) {
+                offset += raw[i] * self.mean[i];
+            }
+        }
+        if let Some((intercept, value)) = self.intercept() {
+            weights[intercept] += offset / value;
+        }
+        weights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folded_weights_give_the_same_scores() {
+        let entries: Vec<_> = (0..20)
+            .map(|x| ComputedEntry {
+                input: SVector::from([
+                    x as f64 / 117.0,
+                    (x % 7) as f64 / 7.0 - 0.3,
+                    (x * x) as f64 / 99.0,
+                    -(x as f64) / 121.0,
+                    ((x * 5) % 11) as f64 / 121.0,
+                    1.0,
+                ]),
+                result: 1.0,
+                turns_until_end: 0,
+                game: x,
+                turn: 0,
+                output: 0.0,
+            })
+            .collect();
+        let standardization = Standardization::fit(&entries);
+        let weights = [0.5, -2.0, 0.25, 3.0, -1.0, 0.1];
+        let raw = standardization.fold(weights);
+        for entry in &entries {
+            let standardized = standardization.apply(&entry.input);
+            let expected = standardized.dot(&SVector::from(weights));
+            assert!((entry.input.dot(&SVector::from(raw)) - expected).abs() < 1e-9);
+        }
+        for (x, y) in standardization.unfold(raw).iter().zip(weights) {
+            assert!((x - y).abs() < 1e-9);
+        }
+    }
+}
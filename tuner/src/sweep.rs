@@ -0,0 +1,392 @@
+//! Running many trainings with different settings and ranking them.
+//!
+//! A sweep spec names config keys, in the dotted form `--set` takes, and the values to try for
+//! each. A grid sweep trains on every combination, a random sweep on `trials` combinations
+//! drawn at random. Each trial is the base config with its values set on top, trained on the
+//! same dataset, and trials run in parallel.
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use rand::{seq::SliceRandom, Rng};
+use rayon::prelude::*;
+use serde::Deserialize;
+use thiserror::Error;
+use toml::Value;
+
+use crate::{
+    config::{Config, ConfigError},
+    dataset::DB,
+    export::WeightsFile,
+    objective::Objective,
+    target, training,
+};
+
+/// Keys that change which positions are in the dataset, which is only built once per sweep.
+const DATASET_KEYS: [&str; 7] = [
+    "db_path",
+    "format",
+    "filter",
+    "perspective",
+    "augment_symmetries",
+    "cache",
+    "on_stale_cache",
+];
+
+#[derive(Debug, Error)]
+pub enum SweepError {
+    #[error("unable to read sweep spec: {0}")]
+    Read(#[from] io::Error),
+    #[error("sweep spec was not well-formatted: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("trial {0}: {1}")]
+    Config(usize, ConfigError),
+    #[error("`{0}` changes the dataset, which a sweep can't do")]
+    DatasetKey(String),
+    #[error("`{0}` is a range, but a grid sweep needs a list of values")]
+    RangeInGrid(String),
+    #[error("`{0}` has no values to try")]
+    NoValues(String),
+    #[error("a random sweep needs `trials`")]
+    MissingTrials,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Search {
+    #[default]
+    Grid,
+    Random,
+}
+
+/// The values one key can take.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Parameter {
+    Values(Vec<Value>),
+    /// only for random sweeps, drawn uniformly, or uniformly on a log scale with `log`
+    Range {
+        min: f64,
+        max: f64,
+        #[serde(default)]
+        log: bool,
+        /// round to a whole number, for keys like `batch_size`
+        #[serde(default)]
+        integer: bool,
+    },
+}
+
+impl Parameter {
+    fn sample<R: Rng>(&self, rng: &mut R) -> Value {
+        match self {
+            Parameter::Values(values) => values.choose(rng).cloned().expect("checked when parsed"),
+            &Parameter::Range {
+                min,
+                max,
+                log,
+                integer,
+            } => {
+                let x = if log {
+                    rng.gen_range(min.ln()..=max.ln()).exp()
+                } else {
+                    rng.gen_range(min..=max)
+                };
+                if integer {
+                    Value::Integer(x.round() as i64)
+                } else {
+                    Value::Float(x)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SweepSpec {
+    #[serde(default)]
+    pub search: Search,
+    /// combinations to try in a random sweep
+    pub trials: Option<usize>,
+    /// trainings run at once, as many as there are cores when it's missing
+    pub parallel: Option<usize>,
+    /// where each trial's weights and checkpoint, and the summary, are written
+    #[serde(default = "default_directory")]
+    pub directory: String,
+    pub parameters: BTreeMap<String, Parameter>,
+}
+
+fn default_directory() -> String {
+    "sweep".to_string()
+}
+
+/// The values one trial sets, in key order.
+pub type Assignment = Vec<(String, Value)>;
+
+impl SweepSpec {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SweepSpec, SweepError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<SweepSpec, SweepError> {
+        let spec: SweepSpec = toml::from_str(text)?;
+        for (key, parameter) in &spec.parameters {
+            let root = key.split('.').next().unwrap_or_default().trim();
+            if DATASET_KEYS.contains(&root) {
+                return Err(SweepError::DatasetKey(key.clone()));
+            }
+            if *parameter == Parameter::Values(vec![]) {
+                return Err(SweepError::NoValues(key.clone()));
+            }
+        }
+        Ok(spec)
+    }
+
+    /// Every trial's values, all combinations for a grid or `trials` draws from `rng`.
+    pub fn assignments<R: Rng>(&self, rng: &mut R) -> Result<Vec<Assignment>, SweepError> {
+        match self.search {
+            Search::Grid => {
+                let mut assignments = vec![vec![]];
+                for (key, parameter) in &self.parameters {
+                    let Parameter::Values(values) = parameter else {
+                        return Err(SweepError::RangeInGrid(key.clone()));
+                    };
+                    assignments = assignments
+                        .into_iter()
+                        .flat_map(|assignment: Assignment| {
+                            values.iter().map(move |value| {
+                                let mut assignment = assignment.clone();
+                                assignment.push((key.clone(), value.clone()));
+                                assignment
+                            })
+                        })
+                        .collect();
+                }
+                Ok(assignments)
+            }
+            Search::Random => {
+                let trials = self.trials.ok_or(SweepError::MissingTrials)?;
+                Ok((0..trials)
+                    .map(|_| {
+                        self.parameters
+                            .iter()
+                            .map(|(key, parameter)| (key.clone(), parameter.sample(rng)))
+                            .collect()
+                    })
+                    .collect())
+            }
+        }
+    }
+}
+
+/// One finished training.
+pub struct Trial {
+    pub index: usize,
+    pub assignment: Assignment,
+    pub weights: WeightsFile,
+}
+
+/// The base config with each assignment set on top, as `key=value` overrides after `overrides`.
+pub fn configs(
+    base: &str,
+    overrides: &[String],
+    assignments: &[Assignment],
+) -> Result<Vec<Config>, SweepError> {
+    assignments
+        .iter()
+        .enumerate()
+        .map(|(index, assignment)| {
+            let overrides: Vec<String> = overrides
+                .iter()
+                .cloned()
+                .chain(
+                    assignment
+                        .iter()
+                        .map(|(key, value)| format!("{key}={value}")),
+                )
+                .collect();
+            Config::parse(base, &overrides).map_err(|e| SweepError::Config(index, e))
+        })
+        .collect()
+}
+
+/// Train every config on `database`, keeping each trial's files in its own directory.
+///
+/// `database` is used as loaded, only the targets are set again for each trial's discount.
+pub fn run(
+    spec: &SweepSpec,
+    assignments: Vec<Assignment>,
+    configs: Vec<Config>,
+    database: &DB,
+    dataset_hash: &str,
+) -> io::Result<Vec<Trial>> {
+    let directory = PathBuf::from(&spec.directory);
+    let trials: Vec<_> = assignments.into_iter().zip(configs).enumerate().collect();
+    let train = |(index, (assignment, mut config)): (usize, (Assignment, Config))| {
+        let trial = directory.join(format!("trial-{index}"));
+        fs::create_dir_all(&trial)?;
+        config.quiet = true;
+        config.resume = false;
+        config.checkpoint = trial.join("checkpoint.json").display().to_string();
+        config.output = trial.join("weights.toml").display().to_string();
+        let mut database = database.clone();
+        for entry in &mut database.entries {
+            entry.output = target(entry.result, entry.turns_until_end, config.discount);
+        }
+        let weights = training::train(&config, &database, dataset_hash.to_string());
+        weights.save(&config.output)?;
+        println!(
+            "trial {index} done: validation {}",
+            weights.metadata.validation
+        );
+        Ok(Trial {
+            index,
+            assignment,
+            weights,
+        })
+    };
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(spec.parallel.unwrap_or(0))
+        .build()
+        .map_err(io::Error::other)?;
+    pool.install(|| trials.into_par_iter().map(train).collect())
+}
+
+/// Trials ranked by validation loss, best first.
+pub struct Summary {
+    pub objective: Objective,
+    pub keys: Vec<String>,
+    pub trials: Vec<Trial>,
+}
+
+impl Summary {
+    /// Rank by `objective`, which should be the base config's so every trial is measured the
+    /// same way, even ones that train with another objective.
+    pub fn new(spec: &SweepSpec, objective: Objective, mut trials: Vec<Trial>) -> Summary {
+        trials.sort_by(|a, b| {
+            objective
+                .of(&a.weights.metadata.validation)
+                .total_cmp(&objective.of(&b.weights.metadata.validation))
+                .then(a.index.cmp(&b.index))
+        });
+        Summary {
+            objective,
+            keys: spec.parameters.keys().cloned().collect(),
+            trials,
+        }
+    }
+
+    pub fn write_csv<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let keys = self.keys.join(",");
+        writeln!(
+            file,
+            "rank,trial,{keys},validation_objective,train_loss,validation_loss,validation_log_loss,validation_accuracy"
+        )?;
+        for (rank, trial) in self.trials.iter().enumerate() {
+            let metadata = &trial.weights.metadata;
+            // values can contain commas, like feature lists, so every one is quoted
+            let values: Vec<_> = trial
+                .assignment
+                .iter()
+                .map(|(_, value)| format!("\"{}\"", value.to_string().replace('"', "\"\"")))
+                .collect();
+            writeln!(
+                file,
+                "{},{},{},{},{},{},{},{}",
+                rank + 1,
+                trial.index,
+                values.join(","),
+                self.objective.of(&metadata.validation),
+                metadata.train.loss,
+                metadata.validation.loss,
+                metadata.validation.log_loss,
+                metadata.validation.accuracy
+            )?;
+        }
+        file.flush()
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>4} {:>5}", "rank", "trial")?;
+        for key in &self.keys {
+            write!(f, " {key:>24}")?;
+        }
+        writeln!(f, " {:>12} {:>10}", "validation", "accuracy")?;
+        for (rank, trial) in self.trials.iter().enumerate() {
+            write!(f, "{:>4} {:>5}", rank + 1, trial.index)?;
+            for (_, value) in &trial.assignment {
+                write!(f, " {:>24}", value.to_string())?;
+            }
+            let validation = &trial.weights.metadata.validation;
+            writeln!(
+                f,
+                " {:>12.6} {:>9.2}%",
+                self.objective.of(validation),
+                validation.accuracy * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    #[test]
+    fn expands_grids_and_draws_random_trials() {
+        let spec = SweepSpec::parse(
+            "[parameters]\n\"optimizer.learning_rate\" = [0.01, 0.1]\nbatch_size = [50, 100, 200]\n",
+        )
+        .unwrap();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let grid = spec.assignments(&mut rng).unwrap();
+        assert_eq!(grid.len(), 6);
+        assert_eq!(
+            grid[1],
+            vec![
+                ("batch_size".to_string(), Value::Integer(50)),
+                ("optimizer.learning_rate".to_string(), Value::Float(0.1)),
+            ]
+        );
+        let base = "weights = [0.0, 0.0, 0.0, 0.0, 0.0, 0.0]\ndb_path = \"\"\n";
+        let expanded = configs(base, &[], &grid).unwrap();
+        assert_eq!(expanded[5].batch_size, 200);
+        assert_eq!(expanded[5].optimizer.learning_rate, 0.1);
+
+        let spec = SweepSpec::parse(
+            "search = \"random\"\ntrials = 20\n[parameters]\n\"optimizer.regularization.l2\" = { min = 1e-4, max = 1e-1, log = true }\nbatch_size = { min = 10, max = 20, integer = true }\nfeatures = [[\"bias\"], [\"bias\", \"length_difference\"]]\n",
+        )
+        .unwrap();
+        let trials = spec.assignments(&mut rng).unwrap();
+        assert_eq!(trials.len(), 20);
+        for config in configs(base, &[], &trials).unwrap() {
+            assert!((1e-4..=1e-1).contains(&config.optimizer.regularization.l2));
+            assert!((10..=20).contains(&config.batch_size));
+            assert!(config.uses("bias"));
+        }
+
+        let spec = SweepSpec::parse("[parameters]\na = { min = 0.0, max = 1.0 }\n").unwrap();
+        assert!(matches!(
+            spec.assignments(&mut rng),
+            Err(SweepError::RangeInGrid(_))
+        ));
+        assert!(matches!(
+            SweepSpec::parse("[parameters]\n\"filter.min_turns\" = [1, 2]\n"),
+            Err(SweepError::DatasetKey(_))
+        ));
+        assert!(matches!(
+            SweepSpec::parse("[parameters]\nepochs = []\n"),
+            Err(SweepError::NoValues(_))
+        ));
+    }
+}
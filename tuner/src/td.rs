@@ -0,0 +1,371 @@
+//! Temporal difference learning from self-play, TD-leaf(λ).
+//!
+//! The current weights play games against themselves. Every position a snake searches is
+//! represented by the leaf of its principal variation, since that is the position the search
+//! actually scored. Each leaf is then trained towards its λ-return: a blend of the values of
+//! the leaves that follow it, with the game result at the end. With λ = 1 that is plain
+//! outcome fitting, and with λ = 0 each leaf only looks at the next one.
+//!
+//! Leaves are kept in a replay buffer in the binary dataset format, so training draws on
+//! earlier games too. Targets are recomputed with the current weights before every round of
+//! training, since they depend on them.
+use std::{fs, io, path::Path};
+
+use eval::{area_eval::AreaEval, search::SearchStatistics, terminal::Terminal};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use snake_tuner::{
+    activation::{functions::Sigmoid, ActivationFunction},
+    dataloader::DataLoader,
+    evaluation::Eval,
+    optimizer::Optimizer,
+};
+
+use crate::{
+    binary::{self, MappedDataset},
+    dataset::{ComputedEntry, DB, FEATURES},
+    metrics::Metrics,
+    optimizer::ScheduledOptimizer,
+    selfplay::{self, SelfPlayConfig},
+};
+
+/// Written into the header of replay buffers, to tell them apart from datasets of real games.
+const BUFFER_METADATA: &str = "td replay buffer";
+
+/// `[td]` section of the tuner config.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TdConfig {
+    pub iterations: usize,
+    /// self-play games per iteration
+    pub games: usize,
+    /// how far ahead the targets look, from 0 for the next leaf only to 1 for the result
+    pub lambda: f64,
+    /// optimizer steps on the replay buffer per iteration
+    pub steps: usize,
+    /// where the replay buffer is kept, it's picked up again by the next run
+    pub buffer: String,
+    /// most positions kept in the replay buffer, the oldest games are dropped first
+    pub capacity: usize,
+    /// where the tuned weights are written, after every iteration
+    pub output: String,
+}
+
+impl Default for TdConfig {
+    fn default() -> Self {
+        TdConfig {
+            iterations: 50,
+            games: 16,
+            lambda: 0.7,
+            steps: 100,
+            buffer: "replay.bin".to_string(),
+            capacity: 200_000,
+            output: "td.toml".to_string(),
+        }
+    }
+}
+
+/// Leaves from recent self-play games.
+///
+/// Every game is stored twice, once per snake, and each of those sequences gets its own game
+/// index. A sequence's entries are contiguous and in turn order, which the targets rely on.
+pub struct ReplayBuffer {
+    pub entries: Vec<ComputedEntry>,
+}
+
+impl ReplayBuffer {
+    /// Open the buffer at `path`, or start an empty one if there isn't one yet.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<ReplayBuffer> {
+        if !path.as_ref().exists() {
+            return Ok(ReplayBuffer { entries: vec![] });
+        }
+        let dataset = MappedDataset::open(path)?;
+        if dataset.metadata() != BUFFER_METADATA || dataset.features() != AreaEval::FEATURES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a replay buffer for the current features",
+            ));
+        }
+        Ok(ReplayBuffer {
+            entries: dataset.to_db().entries,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        binary::write(path, &AreaEval::FEATURES, BUFFER_METADATA, &self.entries)
+    }
+
+    /// Add sequences of leaves, each from one snake's point of view, then drop the oldest
+    /// games until at most `capacity` entries are left.
+    pub fn push(&mut self, sequences: Vec<Vec<ComputedEntry>>, capacity: usize) {
+        let first = self.entries.last().map_or(0, |x| x.game + 1);
+        let sequences = sequences.into_iter().filter(|x| !x.is_empty());
+        for (game, sequence) in (first..).zip(sequences) {
+            self.entries
+                .extend(sequence.into_iter().map(|x| ComputedEntry { game, ..x }));
+        }
+        if self.entries.len() > capacity {
+            let mut cut = self.entries.len() - capacity;
+            // never keep the tail of a game without its start
+            while cut < self.entries.len() && self.entries[cut].game == self.entries[cut - 1].game {
+                cut += 1;
+            }
+            self.entries.drain(..cut);
+        }
+    }
+
+    /// Set every entry's target to its λ-return under `eval`.
+    pub fn set_targets(&mut self, eval: &AreaEval, lambda: f64) {
+        let value = |x: &ComputedEntry| Sigmoid.evaluate(eval.eval.forward(x.input));
+        for sequence in self.entries.chunk_by_mut(|a, b| a.game == b.game) {
+            // the last leaf looks straight at the result
+            let mut target = sequence.last().map_or(0.5, |x| x.result);
+            let mut next = None;
+            for entry in sequence.iter_mut().rev() {
+                if let Some(next) = next {
+                    target = (1.0 - lambda) * next + lambda * target;
+                }
+                entry.output = target;
+                next = Some(value(entry));
+            }
+        }
+    }
+}
+
+pub struct Td {
+    pub config: TdConfig,
+    pub self_play: SelfPlayConfig,
+    pub optimizer: ScheduledOptimizer,
+    pub batch_size: usize,
+    pub eval: AreaEval,
+    pub buffer: ReplayBuffer,
+    /// completed iterations
+    pub iteration: usize,
+    pub seed: u64,
+    rng: ChaCha8Rng,
+}
+
+/// What happened on one iteration.
+pub struct Iteration {
+    /// leaves added to the buffer
+    pub positions: usize,
+    /// mean score of snake `"a"`, which should stay near 0.5 since both sides are the same
+    pub score: f64,
+    pub search: SearchStatistics,
+    /// how well the new weights fit the buffer's targets
+    pub metrics: Metrics,
+}
+
+impl Td {
+    pub fn new(
+        config: TdConfig,
+        self_play: SelfPlayConfig,
+        optimizer: ScheduledOptimizer,
+        batch_size: usize,
+        weights: [f64; FEATURES],
+        buffer: ReplayBuffer,
+        seed: u64,
+    ) -> Td {
+        Td {
+            config,
+            self_play,
+            optimizer,
+            batch_size,
+            eval: AreaEval::new(weights),
+            buffer,
+            iteration: 0,
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Play one iteration's games in parallel, add them to the buffer and train on it.
+    pub fn step(&mut self) -> Iteration {
+        let games: u64 = self.rng.gen();
+        let played: Vec<(f64, SearchStatistics, [Vec<ComputedEntry>; 2])> = (0..self.config.games)
+            .into_par_iter()
+            .map(|game| {
+                let mut rng = ChaCha8Rng::seed_from_u64(games);
+                rng.set_stream(game as u64);
+                let start = selfplay::start(&mut rng);
+                let mut sequences: [Vec<ComputedEntry>; 2] = [vec![], vec![]];
+                let played = selfplay::play_observed(
+                    start,
+                    [&self.eval, &self.eval],
+                    &self.self_play,
+                    &mut rng,
+                    |position, line| {
+                        // finished games have no features, their value is the result itself
+                        if Terminal::detect(&line.leaf).is_some() {
+                            return;
+                        }
+                        let side = (position.you_id != "a") as usize;
+                        sequences[side].push(ComputedEntry {
+                            input: AreaEval::label(&line.leaf),
+                            result: 0.0,
+                            turns_until_end: 0,
+                            game: 0,
+                            turn: position.turn,
+                            output: 0.0,
+                        });
+                    },
+                );
+                let result = played.result;
+                // the game ends on the turn after the last one anybody searched
+                let end = sequences.iter().flatten().map(|x| x.turn + 1).max();
+                for (side, sequence) in sequences.iter_mut().enumerate() {
+                    for entry in sequence {
+                        entry.result = if side == 0 { result } else { 1.0 - result };
+                        entry.turns_until_end = end.unwrap_or(entry.turn) - entry.turn;
+                    }
+                }
+                (result, played.search, sequences)
+            })
+            .collect();
+        let score = played.iter().map(|x| x.0).sum::<f64>() / played.len().max(1) as f64;
+        let search = played.iter().map(|x| x.1).sum();
+        let sequences: Vec<_> = played.into_iter().flat_map(|x| x.2).collect();
+        let positions = sequences.iter().map(Vec::len).sum();
+        self.buffer.push(sequences, self.config.capacity);
+
+        self.buffer.set_targets(&self.eval, self.config.lambda);
+        if !self.buffer.entries.is_empty() {
+            let mut shuffled = DB {
+                entries: self.buffer.entries.clone(),
+            };
+            shuffled.entries.shuffle(&mut self.rng);
+            let mut dataloader = DataLoader::new(shuffled, self.batch_size, false);
+            for _ in 0..self.config.steps {
+                self.optimizer
+                    .step(&mut self.eval.eval, dataloader.sample());
+            }
+        }
+        self.iteration += 1;
+        Iteration {
+            positions,
+            score,
+            search,
+            metrics: Metrics::measure(&self.eval.eval, &self.buffer.entries),
+        }
+    }
+}
+
+/// Weights learnt by TD-leaf, loadable with `AreaEval::load`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TdFile {
+    pub weights: [f64; FEATURES],
+    pub metadata: TdMetadata,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TdMetadata {
+    pub features: Vec<String>,
+    pub iterations: usize,
+    pub seed: u64,
+    pub td: TdConfig,
+    pub self_play: SelfPlayConfig,
+    pub batch_size: usize,
+    /// optimizer settings, and the number of steps taken
+    pub optimizer: ScheduledOptimizer,
+}
+
+impl TdFile {
+    pub fn new(td: &Td) -> TdFile {
+        TdFile {
+            weights: td.eval.weights(),
+            metadata: TdMetadata {
+                features: AreaEval::FEATURES.map(String::from).to_vec(),
+                iterations: td.iteration,
+                seed: td.seed,
+                td: td.config.clone(),
+                self_play: td.self_play,
+                batch_size: td.batch_size,
+                optimizer: td.optimizer.settings(),
+            },
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let text =
+            toml::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::SVector;
+
+    use super::*;
+
+    fn entry(lead: f64, result: f64, turn: u32) -> ComputedEntry {
+        ComputedEntry {
+            input: SVector::from([lead, 0.0, 0.0, 0.0, 0.0, 0.0]),
+            result,
+            turns_until_end: 0,
+            game: 0,
+            turn,
+            output: 0.0,
+        }
+    }
+
+    #[test]
+    fn targets_blend_later_leaves_and_the_result() {
+        let eval = AreaEval::new([1.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        let mut buffer = ReplayBuffer { entries: vec![] };
+        buffer.push(
+            vec![
+                vec![entry(0.0, 1.0, 0), entry(2.0, 1.0, 1)],
+                vec![entry(0.0, 0.0, 0), entry(-2.0, 0.0, 1)],
+            ],
+            100,
+        );
+        assert_eq!(buffer.entries[2].game, 1);
+        let next = Sigmoid.evaluate(2.0);
+
+        buffer.set_targets(&eval, 0.0);
+        assert_eq!(buffer.entries[0].output, next);
+        assert_eq!(buffer.entries[1].output, 1.0);
+        buffer.set_targets(&eval, 1.0);
+        assert_eq!(buffer.entries[0].output, 1.0);
+        buffer.set_targets(&eval, 0.5);
+        assert_eq!(buffer.entries[0].output, 0.5 * next + 0.5);
+        assert!((buffer.entries[2].output - 0.5 * (1.0 - next)).abs() < 1e-12);
+
+        // only whole games are dropped
+        buffer.push(vec![vec![entry(0.0, 0.5, 0)]], 2);
+        assert_eq!(buffer.entries.len(), 1);
+        assert_eq!(buffer.entries[0].game, 2);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("replay.bin");
+        buffer.save(&path).unwrap();
+        assert_eq!(ReplayBuffer::load(&path).unwrap().entries, buffer.entries);
+    }
+
+    #[test]
+    fn learns_from_self_play() {
+        let config = TdConfig {
+            games: 2,
+            steps: 5,
+            ..Default::default()
+        };
+        let mut td = Td::new(
+            config,
+            SelfPlayConfig::default(),
+            ScheduledOptimizer::default(),
+            32,
+            [0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+            ReplayBuffer { entries: vec![] },
+            4,
+        );
+        let iteration = td.step();
+        assert!(iteration.positions > 0);
+        assert_eq!(td.buffer.entries.len(), iteration.positions);
+        assert_ne!(td.eval.weights(), [0.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        // the positions of one game come from both sides
+        assert_eq!(td.buffer.entries.last().unwrap().game, 3);
+    }
+}
@@ -0,0 +1,271 @@
+//! Tuning the weights of the area evaluation on a dataset.
+use eval::area_eval::AreaEval;
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use snake_tuner::{dataloader::DataLoader, evaluation::Eval, optimizer::Optimizer};
+
+use crate::{
+    checkpoint::{Checkpoint, EarlyStopping},
+    config::Config,
+    dataset::DB,
+    export::{Metadata, WeightsFile},
+    metrics::Metrics,
+    objective::fit_scale,
+    split::Split,
+    standardize::Standardization,
+};
+
+// progress output, unless the config asks for quiet
+macro_rules! progress {
+    ($config:expr, $($arg:tt)*) => {
+        if !$config.quiet {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Train on `database` as set up by `config`, saving a checkpoint after every epoch.
+///
+/// Everything random, from the split to the order batches are drawn in, comes from
+/// `config.seed`, so two runs with the same seed on the same dataset give identical weights.
+/// Without a seed, one is picked and recorded in the checkpoint and the returned metadata.
+pub fn train(config: &Config, database: &DB, dataset_hash: String) -> WeightsFile {
+    // features that are left out get a zero weight and input, so they never move from zero
+    let enabled = AreaEval::FEATURES.map(|x| config.uses(x));
+    let mut eval = AreaEval::new(std::array::from_fn(|i| {
+        if enabled[i] {
+            config.weights[i]
+        } else {
+            0.0
+        }
+    }));
+    let mut checkpoint = if config.resume {
+        let checkpoint = Checkpoint::load(&config.checkpoint).expect("Unable to read checkpoint");
+        // the optimizer, batch size and seed carry on from the checkpoint, not the config
+        progress!(
+            config,
+            "resuming from epoch {}, step {}",
+            checkpoint.epoch,
+            checkpoint.optimizer.step
+        );
+        eval = AreaEval::new(checkpoint.weights);
+        checkpoint
+    } else {
+        Checkpoint {
+            version: Checkpoint::VERSION,
+            epoch: 0,
+            weights: eval.weights(),
+            scale: 1.0,
+            standardization: None,
+            optimizer: config.optimizer.clone(),
+            batch_size: config.batch_size,
+            // TOML integers are signed, so keep picked seeds small enough to write back out
+            seed: config.seed.unwrap_or_else(|| rand::random::<u32>().into()),
+            early_stopping: EarlyStopping::new(config.patience, eval.weights()),
+        }
+    };
+    progress!(config, "seed {}", checkpoint.seed);
+
+    let mut split = Split::by_game(&database.entries, config.split, checkpoint.seed);
+    for db in [&mut split.train, &mut split.validation, &mut split.test] {
+        for entry in &mut db.entries {
+            for (i, x) in entry.input.iter_mut().enumerate() {
+                if !enabled[i] {
+                    *x = 0.0;
+                }
+            }
+        }
+    }
+    progress!(
+        config,
+        "{} entries: {} train, {} validation, {} test",
+        database.entries.len(),
+        split.train.entries.len(),
+        split.validation.entries.len(),
+        split.test.entries.len()
+    );
+    let statistics = Standardization::fit(&split.train.entries);
+    for (i, name) in AreaEval::FEATURES.iter().enumerate() {
+        progress!(
+            config,
+            "{name:>28}: mean {:>10.6}, std {:>10.6}",
+            statistics.mean[i],
+            statistics.std[i]
+        );
+    }
+    if !config.resume {
+        if config.fit_scale {
+            let scores: Vec<_> = split
+                .train
+                .entries
+                .iter()
+                .map(|x| (eval.eval.forward(x.input), x.output))
+                .collect();
+            checkpoint.scale = fit_scale(&scores, config.optimizer.objective);
+            progress!(config, "fitted sigmoid scale K = {}", checkpoint.scale);
+            // scaling every weight is the same as scaling the input of the sigmoid
+            eval = AreaEval::new(eval.weights().map(|x| x * checkpoint.scale));
+        }
+        if config.standardize {
+            eval = AreaEval::new(statistics.unfold(eval.weights()));
+            checkpoint.standardization = Some(statistics);
+        }
+        checkpoint.weights = eval.weights();
+        checkpoint.early_stopping = EarlyStopping::new(config.patience, eval.weights());
+    }
+    if let Some(standardization) = &checkpoint.standardization {
+        for db in [&mut split.train, &mut split.validation, &mut split.test] {
+            for entry in &mut db.entries {
+                entry.input = standardization.apply(&entry.input);
+            }
+        }
+    }
+    let steps_per_epoch = split.train.entries.len().div_ceil(checkpoint.batch_size);
+
+    progress!(
+        config,
+        "before: train {}",
+        Metrics::measure(&eval.eval, &split.train.entries)
+    );
+    while checkpoint.epoch < config.epochs {
+        // `DataLoader` shuffles with an unseeded generator, so shuffle here instead. Each epoch
+        // gets its own stream, which keeps resumed runs on the same order as uninterrupted ones.
+        let mut rng = ChaCha8Rng::seed_from_u64(checkpoint.seed);
+        rng.set_stream(checkpoint.epoch as u64);
+        let mut shuffled = split.train.clone();
+        shuffled.entries.shuffle(&mut rng);
+        let mut dataloader = DataLoader::new(shuffled, checkpoint.batch_size, false);
+        for _ in 0..steps_per_epoch {
+            checkpoint
+                .optimizer
+                .step(&mut eval.eval, dataloader.sample());
+        }
+        checkpoint.epoch += 1;
+        checkpoint.weights = eval.weights();
+        let validation = Metrics::measure(&eval.eval, &split.validation.entries);
+        progress!(
+            config,
+            "epoch {} (learning rate {}): train {} | validation {}",
+            checkpoint.epoch,
+            checkpoint.optimizer.current_learning_rate(),
+            Metrics::measure(&eval.eval, &split.train.entries),
+            validation
+        );
+        let stop = checkpoint.early_stopping.update(
+            checkpoint.epoch,
+            checkpoint.optimizer.objective.of(&validation),
+            checkpoint.weights,
+        );
+        checkpoint
+            .save(&config.checkpoint)
+            .expect("Unable to write checkpoint");
+        if stop {
+            progress!(
+                config,
+                "validation loss hasn't improved since epoch {}, stopping",
+                checkpoint.early_stopping.best_epoch
+            );
+            break;
+        }
+    }
+    // keep the weights that did best on the validation set
+    if checkpoint.early_stopping.best_loss.is_some() {
+        eval = AreaEval::new(checkpoint.early_stopping.best_weights);
+    }
+    let metadata = Metadata {
+        features: AreaEval::FEATURES.map(String::from).to_vec(),
+        dataset_hash,
+        seed: checkpoint.seed,
+        scale: checkpoint.scale,
+        standardization: checkpoint.standardization.clone(),
+        batch_size: checkpoint.batch_size,
+        optimizer: checkpoint.optimizer.settings(),
+        train: Metrics::measure(&eval.eval, &split.train.entries),
+        validation: Metrics::measure(&eval.eval, &split.validation.entries),
+        test: Metrics::measure(&eval.eval, &split.test.entries),
+    };
+    progress!(config, "test: {}", metadata.test);
+    // the engine sees raw features, so fold the standardisation back into the weights
+    WeightsFile {
+        weights: match &checkpoint.standardization {
+            Some(standardization) => standardization.fold(eval.weights()),
+            None => eval.weights(),
+        },
+        metadata,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::SVector;
+
+    use crate::{dataset::ComputedEntry, optimizer::Method};
+
+    use super::*;
+
+    // a noisy but learnable dataset, where the first feature predicts the result
+    fn database() -> DB {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let entries = (0..2000)
+            .map(|x| {
+                let lead = rand::Rng::gen_range(&mut rng, -1.0..1.0);
+                let won = rand::Rng::gen_bool(&mut rng, 0.5 + lead * 0.4);
+                ComputedEntry {
+                    input: SVector::from([
+                        lead,
+                        rand::Rng::gen_range(&mut rng, -1.0..1.0),
+                        0.0,
+                        0.0,
+                        0.0,
+                        1.0,
+                    ]),
+                    result: won as u8 as f64,
+                    turns_until_end: 0,
+                    game: x / 20,
+                    turn: x % 20,
+                    output: won as u8 as f64,
+                }
+            })
+            .collect();
+        DB { entries }
+    }
+
+    #[test]
+    fn same_seed_gives_identical_weights() {
+        let dir = tempfile::tempdir().unwrap();
+        let checkpoint = dir.path().join("checkpoint.json");
+        let mut config = Config::parse(
+            "weights = [0.0, 0.0, 0.0, 0.0, 0.0, 0.0]\ndb_path = \"\"\nepochs = 3\nbatch_size = 64\n",
+            &[],
+        )
+        .unwrap();
+        config.checkpoint = checkpoint.to_str().unwrap().to_string();
+        config.seed = Some(42);
+        config.standardize = true;
+        config.optimizer.method = Method::Adam {
+            beta1: 0.9,
+            beta2: 0.999,
+            epsilon: 1e-8,
+            m: vec![],
+            v: vec![],
+            t: 0,
+        };
+        let database = database();
+        let bits = |x: &WeightsFile| x.weights.map(f64::to_bits);
+        let first = train(&config, &database, String::new());
+        let second = train(&config, &database, String::new());
+        assert_eq!(bits(&first), bits(&second));
+        assert!(first.weights[0] > 0.0);
+
+        config.seed = Some(43);
+        let other = train(&config, &database, String::new());
+        assert_ne!(bits(&first), bits(&other));
+
+        // a left out feature keeps a zero weight, even when it starts elsewhere
+        config.weights = [0.0, 0.5, 0.0, 0.0, 0.0, 0.0];
+        config.features = Some(vec!["length_difference".to_string(), "bias".to_string()]);
+        let masked = train(&config, &database, String::new());
+        assert_eq!(masked.weights[1], 0.0);
+        assert!(masked.weights[0] > 0.0);
+    }
+}
@@ -0,0 +1,42 @@
+use std::{fs, process::Command};
+
+use tuner::binary::MappedDataset;
+
+#[test]
+fn converts_a_json_cache() {
+    let temp = tempfile::tempdir().unwrap();
+    let dir = temp.path();
+    let config = "weights = [0.0, 0.0, 0.0, 0.0, 0.0, 0.0]\ndb_path = \"games.db\"\n";
+    fs::write(dir.join("config.toml"), config).unwrap();
+    fs::write(dir.join("games.db"), "not read, the cache is fresh").unwrap();
+    // two games, of three and two positions
+    let entries: Vec<_> = [2, 1, 0, 1, 0]
+        .iter()
+        .map(|turns| {
+            let input = "[1.0, 0.0, 0.0, 0.0, 0.0, 1.0]";
+            format!("{{\"input\": {input}, \"result\": 1.0, \"turns_until_end\": {turns}}}")
+        })
+        .collect();
+    let json = format!("{{\"entries\": [{}]}}", entries.join(", "));
+    fs::write(dir.join("database.json"), json).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tuner"))
+        .current_dir(dir)
+        .args(["build-dataset", "--from-json", "database.json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let dataset = MappedDataset::open(dir.join("database.bin")).unwrap();
+    let games: Vec<_> = (0..dataset.len()).map(|x| dataset.entry(x).game).collect();
+    assert_eq!(games, [0, 0, 0, 1, 1]);
+    // later runs use the converted cache rather than reading the dump
+    let output = Command::new(env!("CARGO_BIN_EXE_tuner"))
+        .current_dir(dir)
+        .arg("build-dataset")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("found old") && stdout.contains("5 entries in database.bin"));
+}